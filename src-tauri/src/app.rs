@@ -1,73 +1,304 @@
 use crate::downloads::{DownloadItem, DownloadStatus};
-use crate::file::download_audio;
+use crate::file::{download_audio, remove_partial};
+use crate::progress::ProgressTracker;
+use crate::settings::Settings;
 use reqwest::Client;
-use std::{collections::HashMap, env::current_dir, error::Error, path::PathBuf};
-use tauri::{App, Emitter, Manager, Runtime, State, async_runtime::spawn as tauri_spawn};
+use std::{
+    collections::{HashMap, VecDeque},
+    env::current_dir,
+    error::Error,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+use tauri::{
+    App, AppHandle, Emitter, Manager, Runtime, State, async_runtime::spawn as tauri_spawn,
+};
 use tokio::{
-    spawn,
+    fs as async_fs, select,
     sync::{
-        Mutex, RwLock,
+        Mutex, Notify, RwLock,
         mpsc::{Receiver, Sender},
     },
+    task::{self, AbortHandle, JoinSet},
 };
 
+#[derive(Default, Debug, serde::Deserialize, serde::Serialize)]
+struct SavedState {
+    downloads: Vec<DownloadItem>,
+    id: usize,
+    directory: Option<PathBuf>,
+    #[serde(default)]
+    settings: Settings,
+}
+
 pub struct DownloadState {
     pub downloads: RwLock<HashMap<usize, DownloadItem>>,
     pub queue: Mutex<Sender<usize>>,
     pub url_id: Mutex<HashMap<String, usize>>,
     pub id: Mutex<usize>,
     pub directory: RwLock<PathBuf>,
+    pub settings: RwLock<Settings>,
+    pub schedule: Notify,
+    pub tasks: Mutex<HashMap<usize, AbortHandle>>,
+    pub restore_error: Mutex<Option<String>>,
+    data_file: Mutex<PathBuf>,
 }
 
 impl DownloadState {
-    pub fn new(tx: Sender<usize>) -> Self {
+    const DATA_FILE: &str = "downloads.json";
+
+    // A missing data file starts an empty queue. One that can't be read is
+    // moved aside first, so the next save doesn't overwrite the only copy of
+    // the list, and the app still launches. The error is kept until the
+    // frontend asks for it.
+    fn restore(tx: Sender<usize>, data_dir: &Path) -> Self {
+        let data_file = data_dir.join(Self::DATA_FILE);
+        let mut restore_error = None;
+        let saved = Self::load(&data_file).unwrap_or_else(|e| {
+            let backup = data_file.with_extension("json.bak");
+            restore_error = Some(match fs::rename(&data_file, &backup) {
+                Ok(()) => format!(
+                    "{e}, starting with an empty list. The old list was moved to {}",
+                    backup.display()
+                ),
+                Err(rename_error) => format!(
+                    "{e}, starting with an empty list. Failed to move it aside: {rename_error}"
+                ),
+            });
+            SavedState::default()
+        });
+        let mut downloads = HashMap::new();
+        let mut url_id = HashMap::new();
+        for mut download in saved.downloads {
+            if matches!(
+                download.status(),
+                DownloadStatus::Queued | DownloadStatus::Downloading
+            ) {
+                download.set_status(DownloadStatus::Initial);
+            }
+            url_id.insert(download.url().to_owned(), download.id());
+            downloads.insert(download.id(), download);
+        }
+        let directory = saved
+            .directory
+            .unwrap_or_else(|| current_dir().unwrap_or_default());
         Self {
-            downloads: RwLock::default(),
+            downloads: RwLock::new(downloads),
             queue: Mutex::new(tx),
-            url_id: Mutex::default(),
-            id: Mutex::default(),
-            directory: RwLock::new(current_dir().unwrap_or_default()),
+            url_id: Mutex::new(url_id),
+            id: Mutex::new(saved.id),
+            directory: RwLock::new(directory),
+            settings: RwLock::new(saved.settings),
+            schedule: Notify::new(),
+            tasks: Mutex::default(),
+            restore_error: Mutex::new(restore_error),
+            data_file: Mutex::new(data_file),
         }
     }
+
+    fn load(data_file: &Path) -> Result<SavedState, String> {
+        let contents = match fs::read(data_file) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(SavedState::default()),
+            Err(e) => return Err(format!("Failed to read {}: {e}", data_file.display())),
+        };
+        serde_json::from_slice(&contents)
+            .map_err(|e| format!("Failed to parse {}: {e}", data_file.display()))
+    }
+
+    // Must not be called while holding any of the locks it reads.
+    pub async fn save(&self) -> Result<(), String> {
+        let data_file = self.data_file.lock().await;
+        let saved = SavedState {
+            downloads: self.downloads.read().await.values().cloned().collect(),
+            id: *self.id.lock().await,
+            directory: Some(self.directory.read().await.clone()),
+            settings: self.settings.read().await.clone(),
+        };
+        let contents = serde_json::to_vec(&saved).map_err(|e| e.to_string())?;
+        if let Some(data_dir) = data_file.parent() {
+            async_fs::create_dir_all(data_dir)
+                .await
+                .map_err(|e| format!("Failed to create data directory: {e}"))?;
+        }
+        let temp_file = data_file.with_extension("json.tmp");
+        async_fs::write(&temp_file, contents)
+            .await
+            .map_err(|e| format!("Failed to save downloads: {e}"))?;
+        async_fs::rename(&temp_file, &*data_file)
+            .await
+            .map_err(|e| format!("Failed to save downloads: {e}"))
+    }
+}
+
+pub fn setup_app(
+    app: &App,
+    tx: Sender<usize>,
+    rx: Receiver<usize>,
+) -> Result<(), Box<dyn Error + 'static>> {
+    let data_dir = app.path().app_data_dir()?;
+    app.manage(DownloadState::restore(tx, &data_dir));
+    tauri_spawn(run_queue(app.handle().clone(), rx));
+    Ok(())
 }
 
-pub fn setup_app(app: &App, mut rx: Receiver<usize>) -> Result<(), Box<dyn Error + 'static>> {
-    let app_handle = app.handle().clone();
+// Ids received from the channel wait in `queued` until both the global and the
+// per-host concurrency limits from the current settings leave room for them.
+async fn run_queue<R: Runtime>(
+    app_handle: AppHandle<R>,
+    mut rx: Receiver<usize>,
+) -> Result<(), String> {
+    let state = app_handle
+        .try_state::<DownloadState>()
+        .ok_or("Could not access application state".to_owned())?;
     let client = Client::new();
-    tauri_spawn(async move {
-        while let Some(id) = rx.recv().await {
-            let app_handle = app_handle.clone();
-            let client = client.clone();
-            spawn(async move {
-                let state = app_handle
-                    .try_state::<DownloadState>()
-                    .ok_or("Could not access application state".to_owned())?;
-                emit_update(id, DownloadStatus::Downloading, None, &state, &app_handle).await?;
-                let (status, failure) = if let Err(e) = download_audio(id, &client, &state).await {
-                    (DownloadStatus::Failed, Some(e))
-                } else {
-                    (DownloadStatus::Completed, None)
+    let mut queued = VecDeque::new();
+    let mut running = JoinSet::new();
+    let mut running_tasks: HashMap<task::Id, (DownloadItem, String)> = HashMap::new();
+    loop {
+        select! {
+            Some(id) = rx.recv() => {
+                let is_queueable = state
+                    .downloads
+                    .read()
+                    .await
+                    .get(&id)
+                    .is_some_and(DownloadItem::is_queueable);
+                // An item stopped while waiting keeps its place in `queued`,
+                // so queueing it again must not add a second entry.
+                if is_queueable {
+                    if !queued.contains(&id) {
+                        queued.push_back(id);
+                    }
+                    emit_update(id, DownloadStatus::Queued, None, &state, &app_handle)
+                        .await
+                        .ok();
+                }
+            }
+            Some(res) = running.join_next_with_id() => {
+                let task_id = match res {
+                    Ok((task_id, _)) => task_id,
+                    Err(e) => e.id(),
                 };
-                emit_update(id, status, failure, &state, &app_handle).await?;
-                Ok::<(), String>(())
-            });
+                if let Some((started, _)) = running_tasks.remove(&task_id) {
+                    let id = started.id();
+                    let mut tasks = state.tasks.lock().await;
+                    if tasks.get(&id).is_some_and(|handle| handle.id() == task_id) {
+                        tasks.remove(&id);
+                    }
+                    drop(tasks);
+                    cleanup_stopped(&started, &state).await.ok();
+                }
+            }
+            () = state.schedule.notified() => {}
+            else => return Ok(()),
         }
-    });
-    Ok(())
+        let settings = state.settings.read().await.clone();
+        let mut index = 0;
+        while running.len() < settings.max_downloads() && index < queued.len() {
+            let id = queued[index];
+            let Some(host) = state
+                .downloads
+                .read()
+                .await
+                .get(&id)
+                .filter(|d| d.is_queued())
+                .map(DownloadItem::host)
+            else {
+                queued.remove(index);
+                continue;
+            };
+            let host_count = running_tasks.values().filter(|(_, h)| *h == host).count();
+            if host_count >= settings.max_downloads_per_host() {
+                index += 1;
+                continue;
+            }
+            queued.remove(index);
+            // The item is marked as downloading before its task is spawned,
+            // and the tasks lock is held until the handle is stored, so a
+            // pause or cancel either still sees it queued or finds the task.
+            let mut tasks = state.tasks.lock().await;
+            let Some(started) = state
+                .downloads
+                .read()
+                .await
+                .get(&id)
+                .filter(|d| d.is_queued())
+                .cloned()
+            else {
+                continue;
+            };
+            emit_update(id, DownloadStatus::Downloading, None, &state, &app_handle)
+                .await
+                .ok();
+            let handle = running.spawn(download(id, client.clone(), app_handle.clone()));
+            running_tasks.insert(handle.id(), (started, host));
+            tasks.insert(id, handle);
+        }
+    }
+}
+
+async fn download<R: Runtime>(
+    id: usize,
+    client: Client,
+    app_handle: AppHandle<R>,
+) -> Result<(), String> {
+    let state = app_handle
+        .try_state::<DownloadState>()
+        .ok_or("Could not access application state".to_owned())?;
+    let mut progress = ProgressTracker::new(id, &app_handle);
+    let result = download_audio(id, &client, &state, &mut progress).await;
+    {
+        let mut downloads = state.downloads.write().await;
+        let Some(download) = downloads
+            .get_mut(&id)
+            .filter(|d| d.status() == DownloadStatus::Downloading)
+        else {
+            return Ok(());
+        };
+        if let Ok((path, size)) = &result {
+            download.set_output(path.clone(), *size);
+        }
+    }
+    let (status, failure) = match result {
+        Ok(_) => (DownloadStatus::Completed, None),
+        Err(e) => (DownloadStatus::Failed, Some(e)),
+    };
+    emit_update(id, status, failure, &state, &app_handle).await
+}
+
+// Partial files are only removed once the aborted task has been dropped, so
+// nothing is still writing to them. Items removed while running are cleaned up
+// using the copy taken when their task started.
+async fn cleanup_stopped(
+    started: &DownloadItem,
+    state: &State<'_, DownloadState>,
+) -> Result<(), String> {
+    let download = match state.downloads.read().await.get(&started.id()) {
+        Some(download) if download.status() == DownloadStatus::Cancelled => download.clone(),
+        Some(_) => return Ok(()),
+        None => started.clone(),
+    };
+    remove_partial(&download, state).await
 }
 
-async fn emit_update<R: Runtime>(
+pub async fn emit_update<R: Runtime>(
     id: usize,
     status: DownloadStatus,
     failure: Option<String>,
     state: &State<'_, DownloadState>,
     manager: &impl Emitter<R>,
 ) -> Result<(), String> {
-    let mut downloads = state.downloads.write().await;
-    let download = downloads.get_mut(&id).ok_or("Invalid download id")?;
-    download.set_status(status);
-    download.set_failure(failure);
+    let download = {
+        let mut downloads = state.downloads.write().await;
+        let download = downloads.get_mut(&id).ok_or("Invalid download id")?;
+        download.set_status(status);
+        download.set_failure(failure);
+        download.clone()
+    };
+    state.save().await?;
     manager
-        .emit::<DownloadItem>("update_downloads", download.clone())
+        .emit::<DownloadItem>("update_downloads", download)
         .map_err(|e| e.to_string())
 }
@@ -1,8 +1,19 @@
 use crate::DownloadState;
-use crate::downloads::{DownloadInput, DownloadItem};
-use std::path::PathBuf;
-use tauri::State;
-use tokio::task::JoinSet;
+use crate::app::emit_update;
+use crate::downloads::{DownloadInput, DownloadItem, DownloadStatus, PreviewEntry};
+use crate::file::{remove_partial, retag_file};
+use crate::parsers::{HostInfo, HostParser, extract_links, parsers};
+use crate::settings::Settings;
+use crate::template::FilenameTemplate;
+use crate::transfer::{ListEntry, ListFormat};
+use reqwest::Client;
+use std::{
+    collections::HashSet, fmt, num::NonZero, path::PathBuf, sync::Arc,
+    thread::available_parallelism,
+};
+use tauri::{AppHandle, State};
+use tauri_plugin_opener::OpenerExt;
+use tokio::{sync::Semaphore, task::JoinSet};
 
 #[tauri::command]
 pub async fn get_downloads(state: State<'_, DownloadState>) -> Result<Vec<DownloadItem>, String> {
@@ -12,23 +23,243 @@ pub async fn get_downloads(state: State<'_, DownloadState>) -> Result<Vec<Downlo
     Ok(downloads_vec)
 }
 
+// Reports a saved download list that could not be restored, once.
+#[tauri::command]
+pub async fn take_restore_error(state: State<'_, DownloadState>) -> Result<Option<String>, String> {
+    Ok(state.restore_error.lock().await.take())
+}
+
 #[tauri::command]
 pub async fn add_download(
     download_input: DownloadInput,
     state: State<'_, DownloadState>,
-) -> Result<DownloadItem, String> {
-    let mut url_id = state.url_id.lock().await;
-    let input_url = download_input.url().to_owned();
-    if url_id.contains_key(&input_url) {
-        return Err(format!("URL already added: {}", &input_url));
+) -> Result<Vec<DownloadItem>, String> {
+    let Some(entries) = download_input.expand().await? else {
+        let mut results = add_inputs(vec![download_input], &state).await?;
+        return results
+            .pop()
+            .ok_or("No download was added".to_owned())?
+            .map(|d| vec![d])
+            .map_err(|e| e.to_string());
+    };
+    add_batch(
+        entries.into_iter().map(PreviewEntry::into_input).collect(),
+        &state,
+    )
+    .await
+}
+
+#[tauri::command]
+pub async fn preview_download(
+    download_input: DownloadInput,
+    state: State<'_, DownloadState>,
+) -> Result<Option<Vec<PreviewEntry>>, String> {
+    let Some(mut entries) = download_input.expand().await? else {
+        return Ok(None);
+    };
+    let url_id = state.url_id.lock().await;
+    for entry in &mut entries {
+        entry.set_added(url_id.contains_key(entry.input().url()));
     }
-    let mut id = state.id.lock().await;
-    let download_item = download_input.parse_input(*id).await?;
-    let mut downloads = state.downloads.write().await;
-    downloads.insert(*id, download_item.clone());
-    url_id.insert(input_url, *id);
-    *id += 1;
-    Ok(download_item)
+    Ok(Some(entries))
+}
+
+#[tauri::command]
+pub async fn add_downloads(
+    download_inputs: Vec<DownloadInput>,
+    state: State<'_, DownloadState>,
+) -> Result<Vec<DownloadItem>, String> {
+    add_batch(download_inputs, &state).await
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct AddResult {
+    url: String,
+    download: Option<DownloadItem>,
+    error: Option<String>,
+}
+
+#[tauri::command]
+pub async fn add_downloads_from_text(
+    text: String,
+    op: String,
+    sub: String,
+    state: State<'_, DownloadState>,
+) -> Result<Vec<AddResult>, String> {
+    let links = extract_links(&text)?;
+    if links.is_empty() {
+        return Err("No supported links found in text".to_owned());
+    }
+    let mut inputs = Vec::new();
+    let mut results = Vec::new();
+    for link in links {
+        let input = DownloadInput::new(link.into(), op.clone(), sub.clone());
+        match input.expand().await {
+            Ok(Some(entries)) => inputs.extend(entries.into_iter().map(PreviewEntry::into_input)),
+            Ok(None) => inputs.push(input),
+            Err(e) => results.push(AddResult {
+                url: input.url().to_owned(),
+                download: None,
+                error: Some(e),
+            }),
+        }
+    }
+    results.extend(add_with_results(inputs, &state).await?);
+    Ok(results)
+}
+
+#[tauri::command]
+pub async fn export_downloads(
+    path: PathBuf,
+    state: State<'_, DownloadState>,
+) -> Result<usize, String> {
+    let format = ListFormat::from_path(&path)?;
+    let mut downloads: Vec<DownloadItem> = state.downloads.read().await.values().cloned().collect();
+    downloads.sort_unstable_by_key(DownloadItem::id);
+    let entries: Vec<ListEntry> = downloads.iter().map(ListEntry::from).collect();
+    tokio::fs::write(&path, format.write(&entries)?)
+        .await
+        .map_err(|e| format!("Failed to write list {}: {e}", path.display()))?;
+    Ok(entries.len())
+}
+
+#[tauri::command]
+pub async fn import_downloads(
+    path: PathBuf,
+    state: State<'_, DownloadState>,
+) -> Result<Vec<AddResult>, String> {
+    let format = ListFormat::from_path(&path)?;
+    let text = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| format!("Failed to read list {}: {e}", path.display()))?;
+    let inputs = format
+        .read(&text)?
+        .into_iter()
+        .map(DownloadInput::from)
+        .collect();
+    add_with_results(inputs, &state).await
+}
+
+async fn add_with_results(
+    inputs: Vec<DownloadInput>,
+    state: &State<'_, DownloadState>,
+) -> Result<Vec<AddResult>, String> {
+    let urls: Vec<String> = inputs.iter().map(|i| i.url().to_owned()).collect();
+    let added = add_inputs(inputs, state).await?;
+    Ok(urls
+        .into_iter()
+        .zip(added)
+        .map(|(url, result)| match result {
+            Ok(download) => AddResult {
+                url,
+                download: Some(download),
+                error: None,
+            },
+            Err(e) => AddResult {
+                url,
+                download: None,
+                error: Some(e.to_string()),
+            },
+        })
+        .collect())
+}
+
+// Duplicates within a batch are skipped silently; the batch only fails when
+// nothing could be added.
+async fn add_batch(
+    inputs: Vec<DownloadInput>,
+    state: &State<'_, DownloadState>,
+) -> Result<Vec<DownloadItem>, String> {
+    let mut downloads = Vec::new();
+    let mut failure = None;
+    for result in add_inputs(inputs, state).await? {
+        match result {
+            Ok(download) => downloads.push(download),
+            Err(AddError::Duplicate(_)) => {}
+            Err(e) => failure = failure.or(Some(e)),
+        }
+    }
+    match failure {
+        Some(e) if downloads.is_empty() => Err(e.to_string()),
+        _ => Ok(downloads),
+    }
+}
+
+enum AddError {
+    Duplicate(String),
+    Failed(String),
+}
+
+impl fmt::Display for AddError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Duplicate(url) => write!(f, "URL already added: {url}"),
+            Self::Failed(e) => f.write_str(e),
+        }
+    }
+}
+
+// Inputs are parsed concurrently outside of the state locks, with ids reserved
+// up front, and results are returned in the same order as the inputs.
+async fn add_inputs(
+    inputs: Vec<DownloadInput>,
+    state: &State<'_, DownloadState>,
+) -> Result<Vec<Result<DownloadItem, AddError>>, String> {
+    let mut results: Vec<Option<Result<DownloadItem, AddError>>> = Vec::new();
+    results.resize_with(inputs.len(), || None);
+    {
+        let url_id = state.url_id.lock().await;
+        let mut seen = HashSet::new();
+        for (i, input) in inputs.iter().enumerate() {
+            if url_id.contains_key(input.url()) || !seen.insert(input.url()) {
+                results[i] = Some(Err(AddError::Duplicate(input.url().to_owned())));
+            }
+        }
+    }
+    let first_id = {
+        let mut id = state.id.lock().await;
+        let first_id = *id;
+        *id += inputs.len();
+        first_id
+    };
+    let permits = Arc::new(Semaphore::new(
+        available_parallelism().map_or(1, NonZero::get),
+    ));
+    let mut set = JoinSet::new();
+    for (i, input) in inputs.into_iter().enumerate() {
+        if results[i].is_some() {
+            continue;
+        }
+        let permits = permits.clone();
+        set.spawn(async move {
+            let _permit = permits.acquire_owned().await;
+            (i, input.parse_input(first_id + i).await)
+        });
+    }
+    let mut parsed = Vec::new();
+    while let Some(res) = set.join_next().await {
+        parsed.push(res.map_err(|e| e.to_string())?);
+    }
+    parsed.sort_unstable_by_key(|(i, _)| *i);
+    {
+        let mut url_id = state.url_id.lock().await;
+        let mut downloads = state.downloads.write().await;
+        for (i, result) in parsed {
+            results[i] = Some(match result {
+                Ok(download) if url_id.contains_key(download.url()) => {
+                    Err(AddError::Duplicate(download.url().to_owned()))
+                }
+                Ok(download) => {
+                    url_id.insert(download.url().to_owned(), download.id());
+                    downloads.insert(download.id(), download.clone());
+                    Ok(download)
+                }
+                Err(e) => Err(AddError::Failed(e)),
+            });
+        }
+    }
+    state.save().await?;
+    Ok(results.into_iter().flatten().collect())
 }
 
 #[tauri::command]
@@ -52,34 +283,78 @@ pub async fn update_download(
         download = download.parse_input().await?;
         url_id.insert(input_url, download.id());
     }
+    if download.status() == DownloadStatus::Initial {
+        download.clear_output();
+    }
     downloads.insert(download.id(), download.clone());
+    drop((url_id, downloads));
+    state.save().await?;
     Ok(download)
 }
 
+// Running items are aborted first, and the queue worker removes their partial
+// files once the task has stopped. Holding the tasks lock keeps queued items
+// from starting while they are removed.
 #[tauri::command]
 pub async fn remove_download(id: usize, state: State<'_, DownloadState>) -> Result<(), String> {
+    let mut tasks = state.tasks.lock().await;
     let mut downloads = state.downloads.write().await;
     let mut url_id = state.url_id.lock().await;
     let download = downloads.remove(&id).ok_or(format!("Invalid id: {id}"))?;
-    let url = download.url();
-    url_id.remove(url);
-    Ok(())
+    url_id.remove(download.url());
+    let aborted = tasks.remove(&id).map(|handle| handle.abort()).is_some();
+    drop((tasks, downloads, url_id));
+    state.save().await?;
+    discard_partial(&download, aborted, &state).await
 }
 
 #[tauri::command]
 pub async fn clear_downloads(state: State<'_, DownloadState>) -> Result<(), String> {
-    state.downloads.write().await.clear();
+    let mut tasks = state.tasks.lock().await;
+    let mut downloads = state.downloads.write().await;
+    let removed: Vec<DownloadItem> = downloads.drain().map(|(_, d)| d).collect();
     state.url_id.lock().await.clear();
+    let aborted: HashSet<usize> = tasks
+        .drain()
+        .map(|(id, handle)| {
+            handle.abort();
+            id
+        })
+        .collect();
+    drop((tasks, downloads));
+    state.save().await?;
+    for download in &removed {
+        discard_partial(download, aborted.contains(&download.id()), &state).await?;
+    }
     Ok(())
 }
 
+// Paused and failed items can leave partial files behind, which nothing else
+// would clean up once the item is gone.
+async fn discard_partial(
+    download: &DownloadItem,
+    aborted: bool,
+    state: &DownloadState,
+) -> Result<(), String> {
+    if aborted
+        || !matches!(
+            download.status(),
+            DownloadStatus::Paused | DownloadStatus::Failed
+        )
+    {
+        return Ok(());
+    }
+    remove_partial(download, state).await
+}
+
 #[tauri::command]
 pub async fn remove_completed(state: State<'_, DownloadState>) -> Result<(), String> {
     let mut downloads = state.downloads.write().await;
     let mut url_id = state.url_id.lock().await;
     downloads.retain(|_, d| !d.is_completed());
     url_id.retain(|_, id| downloads.contains_key(id));
-    Ok(())
+    drop((downloads, url_id));
+    state.save().await
 }
 
 #[tauri::command]
@@ -93,7 +368,7 @@ pub async fn queue_download(
         .await
         .get(&id)
         .ok_or("Invalid Download Item ID provided")?
-        .is_initial()
+        .is_queueable()
     {
         return Err("Download Item is not in correct state".to_owned());
     }
@@ -127,6 +402,221 @@ pub async fn queue_downloads(state: State<'_, DownloadState>) -> Result<(), Stri
     Ok(())
 }
 
+#[tauri::command]
+pub async fn cancel_download(
+    id: usize,
+    state: State<'_, DownloadState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let is_running = stop_download(id, DownloadStatus::Cancelled, &state, &app_handle).await?;
+    if !is_running {
+        let download = state
+            .downloads
+            .read()
+            .await
+            .get(&id)
+            .ok_or(format!("Invalid id: {id}"))?
+            .clone();
+        remove_partial(&download, &state).await?;
+    }
+    Ok(())
+}
+
+#[tauri::command]
+pub async fn pause_download(
+    id: usize,
+    state: State<'_, DownloadState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    stop_download(id, DownloadStatus::Paused, &state, &app_handle).await?;
+    Ok(())
+}
+
+// Returns whether a running task was aborted, in which case the queue worker
+// finishes cleaning up once the task has stopped.
+async fn stop_download(
+    id: usize,
+    status: DownloadStatus,
+    state: &State<'_, DownloadState>,
+    app_handle: &AppHandle,
+) -> Result<bool, String> {
+    // Holding the tasks lock keeps the queue worker from starting the item
+    // between the status change and the abort.
+    let mut tasks = state.tasks.lock().await;
+    let current = state
+        .downloads
+        .read()
+        .await
+        .get(&id)
+        .ok_or(format!("Invalid id: {id}"))?
+        .status();
+    // A paused item has no task left to stop, but can still be cancelled to
+    // discard its partial file.
+    let stoppable = match current {
+        DownloadStatus::Queued | DownloadStatus::Downloading => true,
+        DownloadStatus::Paused => status == DownloadStatus::Cancelled,
+        _ => false,
+    };
+    if !stoppable {
+        return Err("Download is not queued, paused or in progress".to_owned());
+    }
+    emit_update(id, status, None, state, app_handle).await?;
+    Ok(tasks.remove(&id).map(|handle| handle.abort()).is_some())
+}
+
+#[tauri::command]
+pub async fn retry_download(id: usize, state: State<'_, DownloadState>) -> Result<(), String> {
+    {
+        let mut downloads = state.downloads.write().await;
+        let download = downloads.get_mut(&id).ok_or(format!("Invalid id: {id}"))?;
+        if !matches!(
+            download.status(),
+            DownloadStatus::Failed | DownloadStatus::Cancelled
+        ) {
+            return Err("Download Item is not in correct state".to_owned());
+        }
+        download.set_status(DownloadStatus::Initial);
+        download.set_failure(None);
+        download.clear_output();
+    }
+    state.save().await?;
+    state
+        .queue
+        .lock()
+        .await
+        .send(id)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+#[tauri::command]
+pub async fn retag_download(
+    id: usize,
+    rename: bool,
+    state: State<'_, DownloadState>,
+) -> Result<DownloadItem, String> {
+    retag(id, &Client::new(), rename, &state).await
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct RetagResult {
+    id: usize,
+    download: Option<DownloadItem>,
+    error: Option<String>,
+}
+
+// Retags the given items, or every completed item when no ids are given.
+#[tauri::command]
+pub async fn retag_downloads(
+    ids: Vec<usize>,
+    rename: bool,
+    state: State<'_, DownloadState>,
+) -> Result<Vec<RetagResult>, String> {
+    let ids = if ids.is_empty() {
+        let mut completed: Vec<usize> = state
+            .downloads
+            .read()
+            .await
+            .values()
+            .filter(|d| d.is_completed())
+            .map(DownloadItem::id)
+            .collect();
+        completed.sort_unstable();
+        completed
+    } else {
+        ids
+    };
+    let client = Client::new();
+    let mut results = Vec::new();
+    for id in ids {
+        results.push(match retag(id, &client, rename, &state).await {
+            Ok(download) => RetagResult {
+                id,
+                download: Some(download),
+                error: None,
+            },
+            Err(e) => RetagResult {
+                id,
+                download: None,
+                error: Some(e),
+            },
+        });
+    }
+    Ok(results)
+}
+
+async fn retag(
+    id: usize,
+    client: &Client,
+    rename: bool,
+    state: &State<'_, DownloadState>,
+) -> Result<DownloadItem, String> {
+    let download = state
+        .downloads
+        .read()
+        .await
+        .get(&id)
+        .ok_or(format!("Invalid id: {id}"))?
+        .clone();
+    if !download.is_completed() {
+        return Err("Only completed downloads can be retagged".to_owned());
+    }
+    let (path, size) = retag_file(&download, client, state, rename).await?;
+    let download = {
+        let mut downloads = state.downloads.write().await;
+        let download = downloads.get_mut(&id).ok_or(format!("Invalid id: {id}"))?;
+        download.set_retagged_output(path, size);
+        download.clone()
+    };
+    state.save().await?;
+    Ok(download)
+}
+
+#[tauri::command]
+pub async fn open_download(
+    id: usize,
+    state: State<'_, DownloadState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let path = downloaded_path(id, &state).await?;
+    app_handle
+        .opener()
+        .open_path(path.to_string_lossy(), None::<&str>)
+        .map_err(|e| format!("Failed to open {}: {e}", path.display()))
+}
+
+#[tauri::command]
+pub async fn reveal_download(
+    id: usize,
+    state: State<'_, DownloadState>,
+    app_handle: AppHandle,
+) -> Result<(), String> {
+    let path = downloaded_path(id, &state).await?;
+    app_handle
+        .opener()
+        .reveal_item_in_dir(&path)
+        .map_err(|e| format!("Failed to reveal {}: {e}", path.display()))
+}
+
+async fn downloaded_path(id: usize, state: &State<'_, DownloadState>) -> Result<PathBuf, String> {
+    let path = state
+        .downloads
+        .read()
+        .await
+        .get(&id)
+        .ok_or(format!("Invalid id: {id}"))?
+        .path()
+        .ok_or("Download has no recorded file")?
+        .to_owned();
+    if !tokio::fs::try_exists(&path).await.unwrap_or(false) {
+        return Err(format!(
+            "Downloaded file no longer exists: {}",
+            path.display()
+        ));
+    }
+    Ok(path)
+}
+
 #[tauri::command]
 pub async fn get_directory(state: State<'_, DownloadState>) -> Result<String, String> {
     Ok(state.directory.read().await.to_string_lossy().to_string())
@@ -138,5 +628,48 @@ pub async fn set_directory(
     state: State<'_, DownloadState>,
 ) -> Result<(), String> {
     *state.directory.write().await = PathBuf::from(directory);
+    state.save().await
+}
+
+#[tauri::command]
+pub async fn get_settings(state: State<'_, DownloadState>) -> Result<Settings, String> {
+    Ok(state.settings.read().await.clone())
+}
+
+#[tauri::command]
+pub async fn set_settings(
+    settings: Settings,
+    state: State<'_, DownloadState>,
+) -> Result<(), String> {
+    settings.validate()?;
+    *state.settings.write().await = settings;
+    state.save().await?;
+    state.schedule.notify_one();
     Ok(())
 }
+
+// Renders the filename an item would be saved under, using either an unsaved
+// template from the settings form or the current one.
+#[tauri::command]
+pub async fn preview_filename(
+    id: usize,
+    template: Option<FilenameTemplate>,
+    state: State<'_, DownloadState>,
+) -> Result<String, String> {
+    let settings = state.settings.read().await;
+    let template = template.as_ref().unwrap_or(settings.filename_template());
+    let downloads = state.downloads.read().await;
+    let download = downloads.get(&id).ok_or(format!("Invalid id: {id}"))?;
+    let extension = settings
+        .transcode()
+        .format_for(download)
+        .map_or(download.output_extension(), |format| format.extension());
+    download
+        .filename(template, extension)
+        .map(|path| path.display().to_string())
+}
+
+#[tauri::command]
+pub fn get_supported_hosts() -> Vec<HostInfo> {
+    parsers().iter().map(HostParser::info).collect()
+}
@@ -1,9 +1,12 @@
-use crate::parsers::{
-    AudiochanParser, ErocastParser, Parser, SoundgasmParser, VocarooParser, WhypParser,
+use crate::parsers::{Metadata, Parser, find_parser};
+use crate::sanitize::{
+    MAX_FILENAME_BYTES, sanitize_segment, strip_invalid, truncate_bytes, with_extension,
 };
-use regex::Regex;
+use crate::template::{FilenameTemplate, format_date, now};
+use reqwest::Url;
 use std::borrow::ToOwned;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
 #[derive(
     Default,
@@ -20,9 +23,12 @@ use std::collections::HashMap;
 pub enum DownloadStatus {
     #[default]
     Initial,
+    Queued,
     Downloading,
     Completed,
     Failed,
+    Paused,
+    Cancelled,
 }
 
 #[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize)]
@@ -32,6 +38,10 @@ struct DownloadInfo {
     extension: String,
     headers: HashMap<String, String>,
     chunks: Vec<String>,
+    #[serde(default)]
+    uploader: Option<String>,
+    #[serde(default)]
+    metadata: Metadata,
 }
 
 impl From<Box<dyn Parser>> for DownloadInfo {
@@ -41,12 +51,16 @@ impl From<Box<dyn Parser>> for DownloadInfo {
         let extension = parser.extension().to_owned();
         let headers = parser.headers().map_or(HashMap::new(), ToOwned::to_owned);
         let chunks = parser.chunks().map_or(Vec::new(), ToOwned::to_owned);
+        let uploader = parser.uploader().map(ToOwned::to_owned);
+        let metadata = parser.metadata().cloned().unwrap_or_default();
         Self {
             audio,
             title,
             extension,
             headers,
             chunks,
+            uploader,
+            metadata,
         }
     }
 }
@@ -56,51 +70,64 @@ pub struct DownloadInput {
     url: String,
     op: String,
     sub: String,
+    #[serde(default)]
+    track: Option<u32>,
 }
 
 impl DownloadInput {
+    pub fn new(url: String, op: String, sub: String) -> Self {
+        Self {
+            url,
+            op,
+            sub,
+            track: None,
+        }
+    }
+
     pub fn url(&self) -> &str {
         &self.url
     }
 
-    // Hostname regex pattern from URI spec: https://www.rfc-editor.org/rfc/rfc3986#appendix-B
     async fn parse_info(&self) -> Result<DownloadInfo, String> {
-        let url_captures =
-            Regex::new(r"^(([^:\/?#]+):)?(\/\/([^\/?#]*))?([^?#]*)(\?([^#]*))?(#(.*))?")
-                .map_err(|e| e.to_string())
-                .and_then(|re| {
-                    re.captures(&self.url)
-                        .ok_or(format!("Failed to match hostname: {}", &self.url))
-                })?;
-        let hostname = url_captures
-            .get(4)
-            .ok_or(format!("URL contains no valid hostname: {}", &self.url))?
-            .as_str()
-            .to_owned();
-        let parser: Box<dyn Parser> = match () {
-            () if hostname.contains("soundgasm.net") => {
-                Box::new(SoundgasmParser::new(self.url()).await?)
-            }
-            () if hostname.contains("vocaroo.com") => {
-                let id = url_captures
-                    .get(5)
-                    .ok_or(format!("URL contains no valid id: {}", &self.url))?
-                    .as_str();
-                Box::new(VocarooParser::new(id))
-            }
-            () if hostname.contains("audiochan.com") => {
-                Box::new(AudiochanParser::new(self.url()).await?)
-            }
-            () if hostname.contains("whyp.it") => Box::new(WhypParser::new(self.url()).await?),
-            () if hostname.contains("erocast.me") => Box::new(ErocastParser::new(&self.url).await?),
-            () => {
-                return Err(format!(
-                    "URL contains invalid or unsupported host: {}",
-                    &self.url
-                ));
-            }
+        let url = Url::parse(&self.url).map_err(|e| format!("Invalid URL {}: {e}", &self.url))?;
+        let parser = find_parser(&url).ok_or(format!(
+            "URL contains invalid or unsupported host: {}",
+            &self.url
+        ))?;
+        Ok(parser.parse(url).await?.into())
+    }
+
+    pub async fn expand(&self) -> Result<Option<Vec<PreviewEntry>>, String> {
+        let url = Url::parse(&self.url).map_err(|e| format!("Invalid URL {}: {e}", &self.url))?;
+        let parser = find_parser(&url).ok_or(format!(
+            "URL contains invalid or unsupported host: {}",
+            &self.url
+        ))?;
+        let Some(entries) = parser.expand(url).await? else {
+            return Ok(None);
         };
-        Ok(parser.into())
+        Ok(Some(
+            entries
+                .into_iter()
+                .map(|entry| PreviewEntry {
+                    input: Self {
+                        url: entry.url,
+                        op: match entry.op {
+                            Some(op) if self.op.is_empty() => op,
+                            _ => self.op.clone(),
+                        },
+                        sub: match entry.sub {
+                            Some(sub) if self.sub.is_empty() => sub,
+                            _ => self.sub.clone(),
+                        },
+                        track: entry.track,
+                    },
+                    title: entry.title,
+                    duration: entry.duration,
+                    added: false,
+                })
+                .collect(),
+        ))
     }
 
     pub async fn parse_input(self, id: usize) -> Result<DownloadItem, String> {
@@ -109,6 +136,28 @@ impl DownloadInput {
     }
 }
 
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct PreviewEntry {
+    input: DownloadInput,
+    title: Option<String>,
+    duration: Option<f64>,
+    added: bool,
+}
+
+impl PreviewEntry {
+    pub fn input(&self) -> &DownloadInput {
+        &self.input
+    }
+
+    pub fn into_input(self) -> DownloadInput {
+        self.input
+    }
+
+    pub fn set_added(&mut self, added: bool) {
+        self.added = added;
+    }
+}
+
 #[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize)]
 pub struct DownloadItem {
     input: DownloadInput,
@@ -116,16 +165,35 @@ pub struct DownloadItem {
     status: DownloadStatus,
     id: usize,
     failure: Option<String>,
+    #[serde(default)]
+    added_at: u64,
+    #[serde(default)]
+    path: Option<PathBuf>,
+    #[serde(default)]
+    size: Option<u64>,
+    #[serde(default)]
+    completed_at: Option<u64>,
 }
 
 impl DownloadItem {
-    fn new(input: DownloadInput, info: DownloadInfo, id: usize) -> Self {
+    // The uploader only fills in the original poster when the user left it
+    // empty, so manual input always takes precedence.
+    fn new(mut input: DownloadInput, info: DownloadInfo, id: usize) -> Self {
+        if input.op.is_empty()
+            && let Some(uploader) = &info.uploader
+        {
+            input.op = uploader.clone();
+        }
         Self {
             input,
             info,
             status: DownloadStatus::Initial,
             id,
             failure: None,
+            added_at: now(),
+            path: None,
+            size: None,
+            completed_at: None,
         }
     }
 
@@ -145,6 +213,10 @@ impl DownloadItem {
         &self.input.sub
     }
 
+    pub fn track(&self) -> Option<u32> {
+        self.input.track
+    }
+
     pub fn audio(&self) -> &str {
         &self.info.audio
     }
@@ -157,6 +229,17 @@ impl DownloadItem {
         &self.info.extension
     }
 
+    pub fn host(&self) -> String {
+        Url::parse(self.audio())
+            .ok()
+            .and_then(|url| url.host_str().map(ToOwned::to_owned))
+            .unwrap_or_default()
+    }
+
+    pub fn status(&self) -> DownloadStatus {
+        self.status
+    }
+
     pub fn set_status(&mut self, status: DownloadStatus) {
         self.status = status;
     }
@@ -165,6 +248,31 @@ impl DownloadItem {
         self.failure = failure;
     }
 
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    // Records where a freshly downloaded file is and how large it is.
+    pub fn set_output(&mut self, path: PathBuf, size: u64) {
+        self.path = Some(path);
+        self.size = Some(size);
+        self.completed_at = Some(now());
+    }
+
+    // Retagging only rewrites an existing file, so it keeps the time the
+    // download completed.
+    pub fn set_retagged_output(&mut self, path: PathBuf, size: u64) {
+        self.path = Some(path);
+        self.size = Some(size);
+    }
+
+    // An item that goes back to be downloaded again no longer has a file.
+    pub fn clear_output(&mut self) {
+        self.path = None;
+        self.size = None;
+        self.completed_at = None;
+    }
+
     pub fn is_completed(&self) -> bool {
         self.status == DownloadStatus::Completed
     }
@@ -173,6 +281,17 @@ impl DownloadItem {
         self.status == DownloadStatus::Initial
     }
 
+    pub fn is_queueable(&self) -> bool {
+        matches!(
+            self.status,
+            DownloadStatus::Initial | DownloadStatus::Paused
+        )
+    }
+
+    pub fn is_queued(&self) -> bool {
+        self.status == DownloadStatus::Queued
+    }
+
     pub fn id(&self) -> usize {
         self.id
     }
@@ -181,17 +300,107 @@ impl DownloadItem {
         &self.info.headers
     }
 
+    pub fn metadata(&self) -> &Metadata {
+        &self.info.metadata
+    }
+
     pub fn chunks(&self) -> &[String] {
         self.info.chunks.as_slice()
     }
 
-    pub fn filename(&self) -> Result<String, String> {
-        let filename = format!(
-            "[{}] [{}] {}",
-            &self.input.sub, &self.input.op, &self.info.title,
-        );
-        Regex::new(r#"[<>:"/\\\?\*|]+"#)
-            .map_err(|e| format!("Invalid regex pattern: {e}"))
-            .map(|re| re.replace_all(&filename, "").trim().to_owned())
+    // Segmented streams are converted to MP3 once downloaded.
+    pub fn output_extension(&self) -> &str {
+        if self.info.extension == "ts" {
+            "mp3"
+        } else {
+            &self.info.extension
+        }
+    }
+
+    // The host of the page the item was added from, rather than of the audio.
+    pub fn source_host(&self) -> String {
+        Url::parse(&self.input.url)
+            .ok()
+            .and_then(|url| {
+                url.host_str()
+                    .map(|h| h.trim_start_matches("www.").to_owned())
+            })
+            .unwrap_or_default()
+    }
+
+    // Matches the source host exactly or as a subdomain, ignoring `www.`.
+    pub fn is_from_host(&self, host: &str) -> bool {
+        let host = host.trim().trim_start_matches("www.").to_ascii_lowercase();
+        let source = self.source_host().to_ascii_lowercase();
+        source == host || source.ends_with(&format!(".{host}"))
+    }
+
+    // `{ext}` renders the extension of the file being named or tagged, which
+    // differs from the source's once the item is transcoded.
+    pub fn template_value(&self, field: &str, extension: &str) -> String {
+        let metadata = &self.info.metadata;
+        match field {
+            "op" => self.input.op.clone(),
+            "sub" => self.input.sub.clone(),
+            "title" => self.info.title.clone(),
+            "host" => self.source_host(),
+            "id" => self.id.to_string(),
+            "date" => format_date(if self.added_at == 0 {
+                now()
+            } else {
+                self.added_at
+            }),
+            "ext" => extension.to_owned(),
+            "url" => self.input.url.clone(),
+            "track" => self.input.track.map(|t| t.to_string()).unwrap_or_default(),
+            "description" => metadata.description.clone().unwrap_or_default(),
+            "keywords" => metadata.keywords.join(", "),
+            "uploaded" => metadata
+                .date
+                .as_deref()
+                .map(|date| date.get(..10).unwrap_or(date).to_owned())
+                .unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    // Values are sanitized before rendering so only separators written in the
+    // template itself create directories. Each rendered segment is sanitized
+    // again, and the file name leaves room for the number added when renaming
+    // around an existing file.
+    pub fn filename(
+        &self,
+        template: &FilenameTemplate,
+        extension: &str,
+    ) -> Result<PathBuf, String> {
+        let rendered =
+            template.render(|field| strip_invalid(&self.template_value(field, extension)))?;
+        let mut segments: Vec<String> = rendered
+            .split(['/', '\\'])
+            .map(sanitize_segment)
+            .filter(|segment| !segment.is_empty())
+            .map(|segment| {
+                truncate_bytes(&segment, MAX_FILENAME_BYTES)
+                    .trim_end_matches(['.', ' '])
+                    .to_owned()
+            })
+            .collect();
+        let stem = segments.pop().ok_or(format!(
+            "Filename template renders an empty filename for: {}",
+            self.url()
+        ))?;
+        // The extension is always appended, so a template that already ends
+        // in `.{ext}` must not end up with it twice.
+        let stem = stem
+            .strip_suffix(&format!(".{extension}"))
+            .filter(|stem| !stem.is_empty())
+            .unwrap_or(&stem);
+        let reserved = " (9999)".len();
+        segments.push(with_extension(
+            stem,
+            extension,
+            MAX_FILENAME_BYTES - reserved,
+        ));
+        Ok(segments.iter().collect())
     }
 }
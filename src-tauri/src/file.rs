@@ -1,20 +1,86 @@
-use crate::{app::DownloadState, downloads::DownloadItem};
-use ffmpeg_sidecar::{self, command::FfmpegCommand};
-use lofty::{Accessor, ItemKey, Tag, TagExt, TaggedFileExt};
-use reqwest::{Client, header::HeaderMap};
-use std::{collections::HashMap, path::Path};
-use tauri::State;
+use crate::{
+    app::DownloadState,
+    downloads::DownloadItem,
+    progress::ProgressTracker,
+    retry::{AttemptError, RetryPolicy},
+    tags::{fetch_cover, tag_file},
+    template::FilenameTemplate,
+    transcode::{TranscodeFormat, transcode, ts_to_mp3},
+};
+use bytes::Bytes;
+use reqwest::{
+    Client, RequestBuilder, Response, StatusCode,
+    header::{ETAG, HeaderMap, IF_RANGE, LAST_MODIFIED, RANGE},
+};
+use std::{
+    collections::{HashMap, VecDeque},
+    io::{self, ErrorKind, SeekFrom},
+    num::NonZero,
+    path::{Path, PathBuf},
+    thread::available_parallelism,
+};
+use tauri::{Runtime, State};
 use tokio::{
-    fs::{self, File},
-    io::AsyncWriteExt,
-    task::JoinSet,
+    fs::{self, File, OpenOptions},
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    spawn,
+    task::JoinHandle,
 };
 
-pub async fn download_audio(
+// Decides what happens when the output file already exists. Skip keeps the
+// existing file without downloading, while compare downloads first and only
+// keeps the new file under a different name if its contents differ.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+pub enum CollisionPolicy {
+    Skip,
+    Overwrite,
+    #[default]
+    Rename,
+    Compare,
+}
+
+#[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize)]
+struct PartialDownload {
+    url: String,
+    etag: Option<String>,
+    last_modified: Option<String>,
+    offset: u64,
+    chunk: usize,
+}
+
+impl PartialDownload {
+    fn new(url: &str) -> Self {
+        Self {
+            url: url.to_owned(),
+            ..Self::default()
+        }
+    }
+
+    async fn load(path: &Path, url: &str) -> Option<Self> {
+        let contents = fs::read(path).await.ok()?;
+        serde_json::from_slice::<Self>(&contents)
+            .ok()
+            .filter(|partial| partial.url == url)
+    }
+
+    async fn save(&self, path: &Path) -> Result<(), String> {
+        let contents = serde_json::to_vec(self).map_err(|e| e.to_string())?;
+        fs::write(path, contents)
+            .await
+            .map_err(|e| format!("Failed to write partial download info: {e}"))
+    }
+
+    fn validator(&self) -> Option<&str> {
+        self.etag.as_deref().or(self.last_modified.as_deref())
+    }
+}
+
+pub async fn download_audio<R: Runtime>(
     id: usize,
     client: &Client,
     state: &State<'_, DownloadState>,
-) -> Result<(), String> {
+    progress: &mut ProgressTracker<'_, R>,
+) -> Result<(PathBuf, u64), String> {
     let download = state
         .downloads
         .read()
@@ -23,110 +89,499 @@ pub async fn download_audio(
         .ok_or("Invalid download id")?
         .clone();
     let directory = state.directory.read().await.to_owned();
-    let filename = format!("{}.{}", download.filename()?, download.extension());
-    let mut file_path = Path::new(&directory).join(&filename);
-    let mut file = File::create_new(&file_path)
+    let (template, collision, tags, format, keep_original) = {
+        let settings = state.settings.read().await;
+        (
+            settings.filename_template().clone(),
+            settings.collision(),
+            settings.tags().clone(),
+            settings.transcode().format_for(&download),
+            settings.transcode().keep_original(),
+        )
+    };
+    let (file_path, output_path) = download_paths(&download, &directory, &template, format)?;
+    if collision == CollisionPolicy::Skip && exists(&output_path).await? {
+        return output_file(&output_path).await;
+    }
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create directory {}: {e}", parent.display()))?;
+    }
+    let id = download.id();
+    let part_path = partial_path(&output_path, id, "part");
+    let sidecar_path = partial_path(&output_path, id, "part.json");
+    let retry = state.settings.read().await.retry();
+    let mut partial = PartialDownload::load(&sidecar_path, download.audio())
+        .await
+        .unwrap_or_else(|| PartialDownload::new(download.audio()));
+    let mut file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&part_path)
         .await
         .map_err(|e| format!("Failed to create file: {e}"))?;
-    let bytes = {
-        let chunks = download.chunks();
-        if chunks.is_empty() {
-            download_file(download.audio(), download.headers(), client).await?
-        } else {
-            download_chunks(chunks, client).await?
+    let chunks = download.chunks();
+    if chunks.is_empty() {
+        let mut attempt = 0;
+        loop {
+            let res = download_file(
+                download.headers(),
+                client,
+                &mut file,
+                &mut partial,
+                &sidecar_path,
+                progress,
+            )
+            .await;
+            match res {
+                Err(e) if e.is_transient() && retry.backoff(&mut attempt).await => {}
+                res => break res,
+            }
+        }?;
+    } else {
+        download_chunks(
+            chunks,
+            client,
+            &mut file,
+            partial,
+            &sidecar_path,
+            retry,
+            progress,
+        )
+        .await?;
+    }
+    file.flush()
+        .await
+        .map_err(|e| format!("Failed to write data to file: {e}"))?;
+    drop(file);
+    progress.finish()?;
+    let finished_path = match format {
+        Some(format) => {
+            let transcoded_part_path = partial_path(&output_path, id, "conv.part");
+            transcode(&part_path, &transcoded_part_path, format).await?;
+            // A kept original goes next to the output, under its own
+            // extension or with an `.original` suffix if that is the same.
+            if keep_original {
+                let original_path = if file_path == output_path {
+                    let extension = format!("original.{}", download.extension());
+                    directory.join(download.filename(&template, &extension)?)
+                } else {
+                    file_path
+                };
+                place_file(&part_path, &original_path, collision).await?;
+            } else {
+                remove_source(&part_path).await?;
+            }
+            transcoded_part_path
+        }
+        None if output_path != file_path => {
+            let converted_part_path = partial_path(&output_path, id, "conv.part");
+            ts_to_mp3(&part_path, &converted_part_path).await?;
+            remove_source(&part_path).await?;
+            converted_part_path
         }
+        None => part_path,
     };
-    file.write_all(&bytes)
+    let extension = output_path
+        .extension()
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let cover = fetch_cover(client, download.metadata().thumbnail.as_deref()).await;
+    tag_file(
+        &finished_path,
+        &download,
+        &extension,
+        &tags,
+        cover.as_deref(),
+    )?;
+    let placed_path = place_file(&finished_path, &output_path, collision).await?;
+    fs::remove_file(&sidecar_path)
         .await
-        .map_err(|e| format!("Failed to write data to file: {e}"))?;
-    if download.extension() == "ts" {
-        let new_filename = format!("{}.mp3", download.filename()?);
-        let new_file_path = Path::new(&directory).join(&new_filename);
-        ts_to_mp3(&file_path, &new_file_path).await?;
-        file_path = new_file_path;
+        .map_err(|e| format!("Failed to remove partial download info: {e}"))?;
+    output_file(&placed_path).await
+}
+
+async fn remove_source(path: &Path) -> Result<(), String> {
+    fs::remove_file(path)
+        .await
+        .map_err(|e| format!("Failed to remove source file {}: {e}", path.display()))
+}
+
+// Absolute path and size of a finished file, as recorded on its item.
+async fn output_file(path: &Path) -> Result<(PathBuf, u64), String> {
+    let path = std::path::absolute(path)
+        .map_err(|e| format!("Failed to resolve path {}: {e}", path.display()))?;
+    let size = fs::metadata(&path)
+        .await
+        .map_err(|e| format!("Failed to read file {}: {e}", path.display()))?
+        .len();
+    Ok((path, size))
+}
+
+// Rewrites the tags of a completed download from the current settings and,
+// when asked, moves it to the path the filename template now gives it without
+// replacing any other file. Returns the file's new location.
+pub async fn retag_file(
+    download: &DownloadItem,
+    client: &Client,
+    state: &DownloadState,
+    rename: bool,
+) -> Result<(PathBuf, u64), String> {
+    let path = download
+        .path()
+        .ok_or(format!(
+            "No downloaded file recorded for: {}",
+            download.url()
+        ))?
+        .to_owned();
+    if !exists(&path).await? {
+        return Err(format!(
+            "Downloaded file no longer exists: {}",
+            path.display()
+        ));
     }
-    tag_file(&file_path, &download)
+    let directory = state.directory.read().await.clone();
+    let (template, tags) = {
+        let settings = state.settings.read().await;
+        (
+            settings.filename_template().clone(),
+            settings.tags().clone(),
+        )
+    };
+    // The file keeps its format on rename even if transcoding settings have
+    // changed since it was downloaded.
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let cover = fetch_cover(client, download.metadata().thumbnail.as_deref()).await;
+    tag_file(&path, download, &extension, &tags, cover.as_deref())?;
+    let output_path = directory.join(download.filename(&template, &extension)?);
+    if !rename || std::path::absolute(&output_path).is_ok_and(|output_path| output_path == path) {
+        return output_file(&path).await;
+    }
+    if let Some(parent) = output_path.parent() {
+        fs::create_dir_all(parent)
+            .await
+            .map_err(|e| format!("Failed to create directory {}: {e}", parent.display()))?;
+    }
+    let placed_path = place_file(&path, &output_path, CollisionPolicy::Rename).await?;
+    output_file(&placed_path).await
+}
+
+async fn exists(path: &Path) -> Result<bool, String> {
+    fs::try_exists(path)
+        .await
+        .map_err(|e| format!("Failed to check for file {}: {e}", path.display()))
 }
 
-async fn download_file(
-    url: &str,
+// Moves a finished file to its output path according to the collision policy
+// and returns where it ended up.
+async fn place_file(
+    finished_path: &Path,
+    output_path: &Path,
+    collision: CollisionPolicy,
+) -> Result<PathBuf, String> {
+    let target = if !exists(output_path).await? {
+        output_path.to_owned()
+    } else {
+        match collision {
+            CollisionPolicy::Overwrite => output_path.to_owned(),
+            CollisionPolicy::Skip => {
+                discard(finished_path).await?;
+                return Ok(output_path.to_owned());
+            }
+            CollisionPolicy::Compare if same_contents(finished_path, output_path).await? => {
+                discard(finished_path).await?;
+                return Ok(output_path.to_owned());
+            }
+            CollisionPolicy::Rename | CollisionPolicy::Compare => free_path(output_path).await?,
+        }
+    };
+    fs::rename(finished_path, &target)
+        .await
+        .map_err(|e| format!("Failed to move file into place {}: {e}", target.display()))?;
+    Ok(target)
+}
+
+async fn discard(path: &Path) -> Result<(), String> {
+    fs::remove_file(path)
+        .await
+        .map_err(|e| format!("Failed to remove duplicate file {}: {e}", path.display()))
+}
+
+// Finds the first unused `name (n).ext` next to the given path.
+async fn free_path(path: &Path) -> Result<PathBuf, String> {
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    for n in 2.. {
+        let candidate = path.with_file_name(format!("{stem} ({n}){extension}"));
+        if !exists(&candidate).await? {
+            return Ok(candidate);
+        }
+    }
+    unreachable!("ran out of candidate file names")
+}
+
+async fn same_contents(a: &Path, b: &Path) -> Result<bool, String> {
+    let read_error =
+        |path: &Path, e: io::Error| format!("Failed to read file {}: {e}", path.display());
+    let (meta_a, meta_b) = (
+        fs::metadata(a).await.map_err(|e| read_error(a, e))?,
+        fs::metadata(b).await.map_err(|e| read_error(b, e))?,
+    );
+    if meta_a.len() != meta_b.len() {
+        return Ok(false);
+    }
+    let mut file_a = File::open(a).await.map_err(|e| read_error(a, e))?;
+    let mut file_b = File::open(b).await.map_err(|e| read_error(b, e))?;
+    let mut buf_a = vec![0; 64 * 1024];
+    let mut buf_b = vec![0; 64 * 1024];
+    loop {
+        let n = file_a
+            .read(&mut buf_a)
+            .await
+            .map_err(|e| read_error(a, e))?;
+        if n == 0 {
+            return Ok(true);
+        }
+        file_b
+            .read_exact(&mut buf_b[..n])
+            .await
+            .map_err(|e| read_error(b, e))?;
+        if buf_a[..n] != buf_b[..n] {
+            return Ok(false);
+        }
+    }
+}
+
+pub async fn remove_partial(download: &DownloadItem, state: &DownloadState) -> Result<(), String> {
+    let directory = state.directory.read().await.clone();
+    let (template, format) = {
+        let settings = state.settings.read().await;
+        (
+            settings.filename_template().clone(),
+            settings.transcode().format_for(download),
+        )
+    };
+    let (_, output_path) = download_paths(download, &directory, &template, format)?;
+    for suffix in ["part", "part.json", "conv.part"] {
+        let path = partial_path(&output_path, download.id(), suffix);
+        match fs::remove_file(&path).await {
+            Err(e) if e.kind() != ErrorKind::NotFound => {
+                return Err(format!("Failed to remove partial file: {e}"));
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+// Returns the path the raw download would be saved under and the final output
+// path, which differ when the download has to be converted after it completes.
+fn download_paths(
+    download: &DownloadItem,
+    directory: &Path,
+    template: &FilenameTemplate,
+    format: Option<TranscodeFormat>,
+) -> Result<(PathBuf, PathBuf), String> {
+    let output_extension = format.map_or(download.output_extension(), |format| format.extension());
+    let file_path = directory.join(download.filename(template, download.extension())?);
+    let output_path = directory.join(download.filename(template, output_extension)?);
+    Ok((file_path, output_path))
+}
+
+// Partial files are named after the item rather than its output, so items
+// that render the same filename never write to each other's files. The
+// collision policy only applies once the finished file is moved into place.
+fn partial_path(output_path: &Path, id: usize, suffix: &str) -> PathBuf {
+    output_path.with_file_name(format!(".rad-{id}.{suffix}"))
+}
+
+// The partial file is only appended to, so its length is the resume offset as
+// long as the server can prove the resource is unchanged via If-Range.
+async fn download_file<R: Runtime>(
     headers: &HashMap<String, String>,
     client: &Client,
-) -> Result<Vec<u8>, String> {
-    let data = client
-        .get(url)
-        .headers(HeaderMap::try_from(headers).map_err(|e| e.to_string())?)
+    file: &mut File,
+    partial: &mut PartialDownload,
+    sidecar_path: &Path,
+    progress: &mut ProgressTracker<'_, R>,
+) -> Result<(), AttemptError> {
+    let offset = match partial.validator() {
+        Some(_) => file
+            .metadata()
+            .await
+            .map_err(|e| format!("Failed to read partial file: {e}"))?
+            .len(),
+        None => 0,
+    };
+    let request = |offset: u64| -> Result<RequestBuilder, String> {
+        let request = client
+            .get(&partial.url)
+            .headers(HeaderMap::try_from(headers).map_err(|e| e.to_string())?);
+        Ok(match partial.validator() {
+            Some(validator) if offset > 0 => request
+                .header(RANGE, format!("bytes={offset}-"))
+                .header(IF_RANGE, validator),
+            _ => request,
+        })
+    };
+    let mut response = request(offset)?
         .send()
         .await
-        .map_err(|e| format!("Failed to download: {e}"))?;
-    data.bytes()
+        .map_err(|e| AttemptError::request("Failed to download", e))?;
+    if response.status() == StatusCode::RANGE_NOT_SATISFIABLE {
+        response = request(0)?
+            .send()
+            .await
+            .map_err(|e| AttemptError::request("Failed to download", e))?;
+    }
+    let mut response = response
+        .error_for_status()
+        .map_err(|e| AttemptError::request("Failed to download", e))?;
+    if response.status() == StatusCode::PARTIAL_CONTENT {
+        partial.offset = offset;
+    } else {
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(ToOwned::to_owned)
+        };
+        partial.etag = header(ETAG);
+        partial.last_modified = header(LAST_MODIFIED);
+        partial.offset = 0;
+        partial.save(sidecar_path).await?;
+    }
+    file.set_len(partial.offset)
+        .await
+        .map_err(|e| format!("Failed to truncate partial file: {e}"))?;
+    file.seek(SeekFrom::Start(partial.offset))
+        .await
+        .map_err(|e| format!("Failed to seek partial file: {e}"))?;
+    progress.resume_from(partial.offset, 0);
+    progress.set_total(response.content_length().map(|len| partial.offset + len));
+    while let Some(bytes) = response
+        .chunk()
         .await
-        .map_err(|e| format!("Failed to parse to bytes: {e}"))
-        .map(|bytes| bytes.to_vec())
+        .map_err(|e| AttemptError::request("Failed to read response body", e))?
+    {
+        file.write_all(&bytes)
+            .await
+            .map_err(|e| format!("Failed to write data to file: {e}"))?;
+        progress.advance(bytes.len())?;
+    }
+    Ok(())
 }
 
-async fn download_chunks(chunks: &[String], client: &Client) -> Result<Vec<u8>, String> {
-    let mut set = JoinSet::new();
+// Segments are fetched concurrently but written strictly in order, with at most
+// one segment per available thread held in memory at a time.
+async fn download_chunks<R: Runtime>(
+    chunks: &[String],
+    client: &Client,
+    file: &mut File,
+    mut partial: PartialDownload,
+    sidecar_path: &Path,
+    retry: RetryPolicy,
+    progress: &mut ProgressTracker<'_, R>,
+) -> Result<(), String> {
     let size = chunks.len();
-    for (i, chunk) in chunks.iter().enumerate() {
-        let client = client.clone();
-        let chunk = chunk.clone();
-        set.spawn(async move {
-            Ok::<_, String>((
-                i,
-                client
-                    .get(&chunk)
-                    .send()
-                    .await
-                    .map_err(|e| format!("Failed to download chunk {i}/{size} ({chunk}): {e}"))?
-                    .bytes()
-                    .await
-                    .map_err(|e| {
-                        format!("Failed to parse chunk {i}/{size} to bytes ({chunk}): {e}")
-                    })?,
-            ))
-        });
-    }
-    let mut data_chunks = vec![None; size];
-    while let Some(res) = set.join_next().await {
-        let (i, bytes) = res.map_err(|e| e.to_string())??;
-        data_chunks[i] = Some(bytes);
-    }
-    Ok(data_chunks
-        .into_iter()
-        .enumerate()
-        .map(|(i, chunk)| chunk.ok_or(format!("Missing chunk at index {i}/{size}")))
-        .collect::<Result<Vec<_>, _>>()?
-        .concat())
-}
-
-async fn ts_to_mp3(input: &Path, output: &Path) -> Result<(), String> {
-    ffmpeg_sidecar::download::auto_download().map_err(|e| e.to_string())?;
-    FfmpegCommand::new()
-        .input(input.to_string_lossy())
-        .output(output.to_string_lossy())
-        .codec_audio("copy")
-        .no_video()
-        .no_overwrite()
-        .spawn()
-        .map_err(|e| e.to_string())?
-        .wait()
-        .map_err(|e| e.to_string())?
-        .success()
-        .then_some(())
-        .ok_or("Ffmpeg command failed to run")?;
-    fs::remove_file(input).await.map_err(|e| e.to_string())
-}
-
-fn tag_file(file_path: &Path, download: &DownloadItem) -> Result<(), String> {
-    let tagged_file = lofty::read_from_path(file_path)
-        .map_err(|e| format!("Failed to read tags from file: {e}"))?;
-    let mut tag = Tag::new(tagged_file.primary_tag_type());
-    tag.set_artist(download.op().to_owned());
-    tag.set_album(download.op().to_owned());
-    tag.insert_text(ItemKey::AlbumArtist, download.op().to_owned());
-    tag.set_title(download.title().to_owned());
-    tag.set_genre(download.sub().to_owned());
-    tag.save_to_path(file_path)
-        .map_err(|e| format!("Failed to write tags to file: {e}"))
+    progress.set_chunks_total(size);
+    progress.resume_from(partial.offset, partial.chunk);
+    file.set_len(partial.offset)
+        .await
+        .map_err(|e| format!("Failed to truncate partial file: {e}"))?;
+    file.seek(SeekFrom::Start(partial.offset))
+        .await
+        .map_err(|e| format!("Failed to seek partial file: {e}"))?;
+    let window = available_parallelism().map_or(1, NonZero::get);
+    let mut chunks_iter = chunks.iter().enumerate().skip(partial.chunk);
+    let mut pending = VecDeque::with_capacity(window);
+    loop {
+        while pending.len() < window
+            && let Some((i, chunk)) = chunks_iter.next()
+        {
+            let client = client.clone();
+            let chunk = chunk.clone();
+            let handle = ChunkTask(spawn(async move {
+                let mut attempt = 0;
+                loop {
+                    match download_chunk(i, size, &chunk, &client).await {
+                        Err(e) if e.is_transient() && retry.backoff(&mut attempt).await => {}
+                        res => break res.map_err(String::from),
+                    }
+                }
+            }));
+            pending.push_back((i, handle));
+        }
+        let Some((i, mut handle)) = pending.pop_front() else {
+            return Ok(());
+        };
+        let written = match (&mut handle.0).await {
+            Ok(Ok(bytes)) => write_chunk(&bytes, file, &mut partial, sidecar_path)
+                .await
+                .map_err(|e| format!("Failed to write chunk {i}/{size} to file: {e}"))
+                .and_then(|()| progress.advance_chunk(bytes.len())),
+            Ok(Err(e)) => Err(e),
+            Err(e) => Err(e.to_string()),
+        };
+        written?;
+    }
+}
+
+// Aborts the segment fetch when dropped, so pending segments stop along with
+// the download task instead of running on detached.
+struct ChunkTask(JoinHandle<Result<Bytes, String>>);
+
+impl Drop for ChunkTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+async fn download_chunk(
+    i: usize,
+    size: usize,
+    chunk: &str,
+    client: &Client,
+) -> Result<Bytes, AttemptError> {
+    client
+        .get(chunk)
+        .send()
+        .await
+        .and_then(Response::error_for_status)
+        .map_err(|e| {
+            AttemptError::request(&format!("Failed to download chunk {i}/{size} ({chunk})"), e)
+        })?
+        .bytes()
+        .await
+        .map_err(|e| {
+            AttemptError::request(
+                &format!("Failed to parse chunk {i}/{size} to bytes ({chunk})"),
+                e,
+            )
+        })
+}
+
+async fn write_chunk(
+    bytes: &[u8],
+    file: &mut File,
+    partial: &mut PartialDownload,
+    sidecar_path: &Path,
+) -> Result<(), String> {
+    file.write_all(bytes).await.map_err(|e| e.to_string())?;
+    file.flush().await.map_err(|e| e.to_string())?;
+    partial.offset += bytes.len() as u64;
+    partial.chunk += 1;
+    partial.save(sidecar_path).await
 }
@@ -6,10 +6,22 @@ mod commands;
 mod downloads;
 mod file;
 mod parsers;
+mod progress;
+mod retry;
+mod sanitize;
+mod settings;
+mod tags;
+mod template;
+mod transcode;
+mod transfer;
 use app::{DownloadState, setup_app};
 use commands::{
-    add_download, clear_downloads, get_directory, get_downloads, queue_download, queue_downloads,
-    remove_completed, remove_download, set_directory, update_download,
+    add_download, add_downloads, add_downloads_from_text, cancel_download, clear_downloads,
+    export_downloads, get_directory, get_downloads, get_settings, get_supported_hosts,
+    import_downloads, open_download, pause_download, preview_download, preview_filename,
+    queue_download, queue_downloads, remove_completed, remove_download, retag_download,
+    retag_downloads, retry_download, reveal_download, set_directory, set_settings,
+    take_restore_error, update_download,
 };
 use tokio::sync::mpsc;
 
@@ -19,19 +31,35 @@ fn main() -> Result<(), tauri::Error> {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
         .plugin(tauri_plugin_opener::init())
-        .manage::<DownloadState>(DownloadState::new(tx))
         .invoke_handler(tauri::generate_handler![
             get_downloads,
+            take_restore_error,
             add_download,
+            add_downloads,
+            add_downloads_from_text,
+            preview_download,
             update_download,
             remove_download,
             clear_downloads,
             remove_completed,
+            import_downloads,
+            export_downloads,
             queue_download,
             queue_downloads,
+            cancel_download,
+            pause_download,
+            retry_download,
+            retag_download,
+            retag_downloads,
+            open_download,
+            reveal_download,
             get_directory,
             set_directory,
+            get_settings,
+            set_settings,
+            get_supported_hosts,
+            preview_filename,
         ])
-        .setup(move |app| setup_app(app, rx))
+        .setup(move |app| setup_app(app, tx, rx))
         .run(tauri::generate_context!())
 }
@@ -1,13 +1,18 @@
 use regex::Regex;
 use reqwest::{
-    Client,
+    Client, Url,
     header::{ACCEPT, HeaderMap, InvalidHeaderValue, REFERER, USER_AGENT},
 };
 use scraper::{Html, Selector};
 use serde_json::Value;
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    pin::Pin,
+    sync::LazyLock,
+};
 
-pub trait Parser {
+pub trait Parser: Send {
     fn audio(&self) -> &str;
     fn title(&self) -> &str;
     fn extension(&self) -> &str;
@@ -19,29 +24,279 @@ pub trait Parser {
     fn headers(&self) -> Option<&HashMap<String, String>> {
         None
     }
+
+    fn uploader(&self) -> Option<&str> {
+        None
+    }
+
+    fn metadata(&self) -> Option<&Metadata> {
+        None
+    }
+
+    fn name() -> &'static str
+    where
+        Self: Sized;
+
+    // Domains are matched exactly or as a parent domain, so "soundgasm.net"
+    // matches "www.soundgasm.net" but not "notsoundgasm.net".
+    fn hosts() -> &'static [&'static str]
+    where
+        Self: Sized;
+
+    fn examples() -> &'static [&'static str]
+    where
+        Self: Sized;
+
+    fn capabilities() -> Capabilities
+    where
+        Self: Sized,
+    {
+        Capabilities {
+            has_title: true,
+            ..Capabilities::default()
+        }
+    }
+
+    fn from_url(url: &Url) -> impl Future<Output = Result<Self, String>> + Send
+    where
+        Self: Sized;
+
+    // Profile and playlist URLs expand into one entry per audio page, while
+    // URLs for a single audio return `None`.
+    fn expand(_url: &Url) -> impl Future<Output = Result<Option<Vec<PlaylistEntry>>, String>> + Send
+    where
+        Self: Sized,
+    {
+        async { Ok(None) }
+    }
+}
+
+#[derive(Default, Debug, Clone)]
+pub struct PlaylistEntry {
+    pub url: String,
+    pub op: Option<String>,
+    pub sub: Option<String>,
+    pub track: Option<u32>,
+    pub title: Option<String>,
+    pub duration: Option<f64>,
+}
+
+#[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct Metadata {
+    pub description: Option<String>,
+    pub date: Option<String>,
+    pub keywords: Vec<String>,
+    pub thumbnail: Option<String>,
+}
+
+impl Metadata {
+    // Hosts name these fields differently, so the keys for each field are
+    // passed in and missing or empty values are ignored.
+    fn from_json(
+        json: &Value,
+        description: &str,
+        date: &str,
+        keywords: &str,
+        thumbnail: &str,
+    ) -> Self {
+        let text = |key: &str| {
+            json[key]
+                .as_str()
+                .map(str::trim)
+                .filter(|value| !value.is_empty())
+                .map(ToOwned::to_owned)
+        };
+        let keywords = json[keywords]
+            .as_array()
+            .map(|tags| {
+                tags.iter()
+                    .filter_map(|tag| tag.as_str().or(tag["name"].as_str()))
+                    .map(ToOwned::to_owned)
+                    .collect()
+            })
+            .unwrap_or_default();
+        Self {
+            description: text(description),
+            date: text(date),
+            keywords,
+            thumbnail: text(thumbnail),
+        }
+    }
+}
+
+#[derive(Default, Debug, Clone, Copy, serde::Serialize)]
+pub struct Capabilities {
+    has_title: bool,
+    provides_chunks: bool,
+    needs_headers: bool,
+    supports_playlists: bool,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct HostInfo {
+    name: &'static str,
+    hosts: &'static [&'static str],
+    examples: &'static [&'static str],
+    capabilities: Capabilities,
+}
+
+// Audio URL, title, extension or segments, uploader and metadata as read from
+// a host's API.
+type ParsedInfo = (String, String, String, Option<String>, Metadata);
+type ChunkedInfo = (String, String, Vec<String>, Option<String>, Metadata);
+
+type ParserFuture = Pin<Box<dyn Future<Output = Result<Box<dyn Parser>, String>> + Send>>;
+type ExpandFuture =
+    Pin<Box<dyn Future<Output = Result<Option<Vec<PlaylistEntry>>, String>> + Send>>;
+
+pub struct HostParser {
+    name: &'static str,
+    hosts: &'static [&'static str],
+    examples: &'static [&'static str],
+    capabilities: Capabilities,
+    parse: fn(Url) -> ParserFuture,
+    expand: fn(Url) -> ExpandFuture,
+}
+
+impl HostParser {
+    fn of<P: Parser + 'static>() -> Self {
+        Self {
+            name: P::name(),
+            hosts: P::hosts(),
+            examples: P::examples(),
+            capabilities: P::capabilities(),
+            parse: |url| {
+                Box::pin(async move { Ok(Box::new(P::from_url(&url).await?) as Box<dyn Parser>) })
+            },
+            expand: |url| Box::pin(async move { P::expand(&url).await }),
+        }
+    }
+
+    pub fn info(&self) -> HostInfo {
+        HostInfo {
+            name: self.name,
+            hosts: self.hosts,
+            examples: self.examples,
+            capabilities: self.capabilities,
+        }
+    }
+
+    pub fn matches(&self, url: &Url) -> bool {
+        let Some(host) = url.host_str() else {
+            return false;
+        };
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.hosts.iter().any(|domain| {
+            host == *domain
+                || host
+                    .strip_suffix(domain)
+                    .is_some_and(|subdomain| subdomain.ends_with('.'))
+        })
+    }
+
+    pub async fn parse(&self, url: Url) -> Result<Box<dyn Parser>, String> {
+        (self.parse)(url).await
+    }
+
+    pub async fn expand(&self, url: Url) -> Result<Option<Vec<PlaylistEntry>>, String> {
+        (self.expand)(url).await
+    }
+}
+
+static PARSERS: LazyLock<Vec<HostParser>> = LazyLock::new(|| {
+    vec![
+        HostParser::of::<SoundgasmParser>(),
+        HostParser::of::<VocarooParser>(),
+        HostParser::of::<AudiochanParser>(),
+        HostParser::of::<WhypParser>(),
+        HostParser::of::<ErocastParser>(),
+        HostParser::of::<RedditParser>(),
+    ]
+});
+
+pub fn parsers() -> &'static [HostParser] {
+    &PARSERS
+}
+
+pub fn find_parser(url: &Url) -> Option<&'static HostParser> {
+    parsers().iter().find(|parser| parser.matches(url))
+}
+
+// Scans free text, markdown or HTML for links to supported hosts, in order of
+// appearance and without duplicates.
+pub fn extract_links(text: &str) -> Result<Vec<Url>, String> {
+    let link_regex = Regex::new(r#"https?://[^\s\[\]()<>"'|]+"#).map_err(|e| e.to_string())?;
+    let mut seen = HashSet::new();
+    Ok(link_regex
+        .find_iter(text)
+        .map(|m| m.as_str().replace("&amp;", "&"))
+        .filter_map(|link| Url::parse(link.trim_end_matches(['.', ',', '*', ';'])).ok())
+        .filter(|link| find_parser(link).is_some())
+        .filter(|link| seen.insert(link.to_string()))
+        .collect())
 }
 
 pub struct SoundgasmParser {
     audio: String,
     title: String,
     extension: String,
+    uploader: Option<String>,
+    metadata: Metadata,
 }
 
 impl SoundgasmParser {
     const AUDIO_REGEX: &str = r#"(https:\/\/media\.soundgasm\.net\/sounds\/[^\r\n\t\f\v"]+)"#;
     const TITLE_SELECTOR: &str = "div.jp-title";
+    const DESCRIPTION_SELECTOR: &str = "div.jp-description";
 
-    pub async fn new(url: &str) -> Result<Self, String> {
-        let (audio, title, extension) = Self::parse_info(url).await?;
+    pub async fn new(url: &Url) -> Result<Self, String> {
+        let (audio, title, extension, metadata) = Self::parse_info(url.as_str()).await?;
+        let uploader = match path_segments(url).as_slice() {
+            ["u", user, ..] => Some((*user).to_owned()),
+            _ => None,
+        };
         Ok(Self {
             audio,
             title,
             extension,
+            uploader,
+            metadata,
         })
     }
 
-    async fn parse_info(url: &str) -> Result<(String, String, String), String> {
-        info_from_page(url, Self::AUDIO_REGEX, Self::TITLE_SELECTOR).await
+    async fn parse_info(url: &str) -> Result<(String, String, String, Metadata), String> {
+        info_from_page(
+            url,
+            Self::AUDIO_REGEX,
+            Self::TITLE_SELECTOR,
+            Self::DESCRIPTION_SELECTOR,
+        )
+        .await
+    }
+
+    async fn parse_profile(url: &Url, user: &str) -> Result<Vec<PlaylistEntry>, String> {
+        let html = reqwest::get(url.as_str())
+            .await
+            .map_err(|e| format!("Failed to fetch page {url}: {e}"))?
+            .text()
+            .await
+            .map_err(|e| format!("Failed to parse HTML to text {url}: {e}"))?;
+        let audio_regex = format!(
+            r#"https:\/\/soundgasm\.net\/u\/{}\/[^\r\n\t\f\v"'#?\/]+"#,
+            regex::escape(user)
+        );
+        let mut seen = HashSet::new();
+        Ok(Regex::new(&audio_regex)
+            .map_err(|e| e.to_string())?
+            .find_iter(&html)
+            .map(|m| m.as_str().to_owned())
+            .filter(|audio_url| seen.insert(audio_url.clone()))
+            .map(|audio_url| PlaylistEntry {
+                url: audio_url,
+                op: Some(user.to_owned()),
+                ..PlaylistEntry::default()
+            })
+            .collect())
     }
 }
 
@@ -57,6 +312,48 @@ impl Parser for SoundgasmParser {
     fn extension(&self) -> &str {
         &self.extension
     }
+
+    fn uploader(&self) -> Option<&str> {
+        self.uploader.as_deref()
+    }
+
+    fn metadata(&self) -> Option<&Metadata> {
+        Some(&self.metadata)
+    }
+
+    fn name() -> &'static str {
+        "Soundgasm"
+    }
+
+    fn hosts() -> &'static [&'static str] {
+        &["soundgasm.net"]
+    }
+
+    fn examples() -> &'static [&'static str] {
+        &[
+            "https://soundgasm.net/u/<user>/<title>",
+            "https://soundgasm.net/u/<user>",
+        ]
+    }
+
+    async fn from_url(url: &Url) -> Result<Self, String> {
+        Self::new(url).await
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            has_title: true,
+            supports_playlists: true,
+            ..Capabilities::default()
+        }
+    }
+
+    async fn expand(url: &Url) -> Result<Option<Vec<PlaylistEntry>>, String> {
+        match path_segments(url).as_slice() {
+            ["u", user] => Ok(Some(Self::parse_profile(url, user).await?)),
+            _ => Ok(None),
+        }
+    }
 }
 
 pub struct VocarooParser {
@@ -89,25 +386,52 @@ impl Parser for VocarooParser {
     fn headers(&self) -> Option<&HashMap<String, String>> {
         Some(&self.headers)
     }
+
+    fn name() -> &'static str {
+        "Vocaroo"
+    }
+
+    fn hosts() -> &'static [&'static str] {
+        &["vocaroo.com", "voca.ro"]
+    }
+
+    fn examples() -> &'static [&'static str] {
+        &["https://vocaroo.com/<id>", "https://voca.ro/<id>"]
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            needs_headers: true,
+            ..Capabilities::default()
+        }
+    }
+
+    async fn from_url(url: &Url) -> Result<Self, String> {
+        Ok(Self::new(url.path()))
+    }
 }
 
 pub struct AudiochanParser {
     audio: String,
     title: String,
     extension: String,
+    uploader: Option<String>,
+    metadata: Metadata,
 }
 
 impl AudiochanParser {
     pub async fn new(url: &str) -> Result<Self, String> {
-        let (audio, title, extension) = Self::parse_info(url).await?;
+        let (audio, title, extension, uploader, metadata) = Self::parse_info(url).await?;
         Ok(Self {
             audio,
             title,
             extension,
+            uploader,
+            metadata,
         })
     }
 
-    async fn parse_info(url: &str) -> Result<(String, String, String), String> {
+    async fn parse_info(url: &str) -> Result<ParsedInfo, String> {
         let base_url = "audiochan.com";
         let slug = url
             .split('/')
@@ -138,7 +462,55 @@ impl AudiochanParser {
             .next_back()
             .ok_or(format!("Failed to parse extension from JSON: {filename}"))?
             .to_owned();
-        Ok((audio, title, extension))
+        let user = &json["user"];
+        let uploader = user["displayName"]
+            .as_str()
+            .or(user["slug"].as_str())
+            .map(ToOwned::to_owned);
+        let metadata = Metadata::from_json(&json, "description", "createdAt", "tags", "image");
+        Ok((audio, title, extension, uploader, metadata))
+    }
+
+    async fn parse_creator(slug: &str) -> Result<Vec<PlaylistEntry>, String> {
+        let base_url = "audiochan.com";
+        let mut url = Url::parse(&format!("https://api.{base_url}/users/slug/{slug}/audios"))
+            .map_err(|e| e.to_string())?;
+        let mut seen = HashSet::from([url.clone()]);
+        let mut entries = Vec::new();
+        loop {
+            let body = reqwest::get(url.as_str())
+                .await
+                .map_err(|e| e.to_string())?
+                .text()
+                .await
+                .map_err(|e| e.to_string())?;
+            let json: Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+            let audios = json
+                .as_array()
+                .or(json["data"].as_array())
+                .ok_or("Audios are missing from API response")?;
+            for audio in audios {
+                let audio_slug = audio["slug"]
+                    .as_str()
+                    .ok_or("Audio slug is missing from API response")?;
+                // The slug in the URL is not the name the creator goes by.
+                // Without a display name the uploader from the audio's own
+                // page fills in the original poster instead.
+                entries.push(PlaylistEntry {
+                    url: format!("https://{base_url}/a/{audio_slug}"),
+                    op: audio["user"]["displayName"].as_str().map(ToOwned::to_owned),
+                    title: audio["title"].as_str().map(ToOwned::to_owned),
+                    duration: audio["audioFile"]["duration"]
+                        .as_f64()
+                        .or(audio["duration"].as_f64()),
+                    ..PlaylistEntry::default()
+                });
+            }
+            match next_page(&json, &url) {
+                Some(next) if seen.insert(next.clone()) => url = next,
+                _ => return Ok(entries),
+            }
+        }
     }
 }
 
@@ -154,25 +526,71 @@ impl Parser for AudiochanParser {
     fn extension(&self) -> &str {
         &self.extension
     }
+
+    fn uploader(&self) -> Option<&str> {
+        self.uploader.as_deref()
+    }
+
+    fn metadata(&self) -> Option<&Metadata> {
+        Some(&self.metadata)
+    }
+
+    fn name() -> &'static str {
+        "Audiochan"
+    }
+
+    fn hosts() -> &'static [&'static str] {
+        &["audiochan.com"]
+    }
+
+    fn examples() -> &'static [&'static str] {
+        &[
+            "https://audiochan.com/a/<slug>",
+            "https://audiochan.com/u/<slug>",
+        ]
+    }
+
+    async fn from_url(url: &Url) -> Result<Self, String> {
+        Self::new(url.as_str()).await
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            has_title: true,
+            supports_playlists: true,
+            ..Capabilities::default()
+        }
+    }
+
+    async fn expand(url: &Url) -> Result<Option<Vec<PlaylistEntry>>, String> {
+        match path_segments(url).as_slice() {
+            ["u", slug] => Ok(Some(Self::parse_creator(slug).await?)),
+            _ => Ok(None),
+        }
+    }
 }
 
 pub struct WhypParser {
     audio: String,
     title: String,
     extension: String,
+    uploader: Option<String>,
+    metadata: Metadata,
 }
 
 impl WhypParser {
     pub async fn new(url: &str) -> Result<Self, String> {
-        let (audio, title, extension) = Self::parse_info(url).await?;
+        let (audio, title, extension, uploader, metadata) = Self::parse_info(url).await?;
         Ok(Self {
             audio,
             title,
             extension,
+            uploader,
+            metadata,
         })
     }
 
-    async fn parse_info(url: &str) -> Result<(String, String, String), String> {
+    async fn parse_info(url: &str) -> Result<ParsedInfo, String> {
         let id = {
             let mut slash_parts = url.split('/');
             let (_slug, id) = (slash_parts.next_back(), slash_parts.next_back());
@@ -214,7 +632,57 @@ impl WhypParser {
                 "Failed to get file extension from audio URL: {audio}"
             ))?
             .to_owned();
-        Ok((audio, title, extension))
+        let uploader = track["user"]["username"].as_str().map(ToOwned::to_owned);
+        let metadata =
+            Metadata::from_json(track, "description", "created_at", "tags", "artwork_url");
+        Ok((audio, title, extension, uploader, metadata))
+    }
+
+    async fn parse_tracks(url: &Url, kind: &str, id: &str) -> Result<Vec<PlaylistEntry>, String> {
+        let query = url
+            .query()
+            .map(|query| format!("?{query}"))
+            .unwrap_or_default();
+        let base_url = "https://api.whyp.it";
+        let mut page_url = Url::parse(&format!("{base_url}/api/{kind}/{id}/tracks{query}"))
+            .map_err(|e| e.to_string())?;
+        let mut seen = HashSet::from([page_url.clone()]);
+        let is_playlist = kind == "playlists";
+        let mut entries = Vec::new();
+        let mut numbers = 1..;
+        loop {
+            let body = reqwest::get(page_url.as_str())
+                .await
+                .map_err(|e| e.to_string())?
+                .text()
+                .await
+                .map_err(|e| e.to_string())?;
+            let json: Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+            let tracks = json["tracks"]
+                .as_array()
+                .ok_or("Tracks are missing from API response")?;
+            // Numbering carries on across pages, so a playlist keeps its order.
+            for (track, number) in tracks.iter().zip(&mut numbers) {
+                let track_id = track["id"]
+                    .as_u64()
+                    .ok_or("Track id is missing from API response")?;
+                let slug = track["slug"]
+                    .as_str()
+                    .ok_or("Track slug is missing from API response")?;
+                entries.push(PlaylistEntry {
+                    url: format!("https://whyp.it/tracks/{track_id}/{slug}{query}"),
+                    op: track["user"]["username"].as_str().map(ToOwned::to_owned),
+                    track: is_playlist.then_some(number),
+                    title: track["title"].as_str().map(ToOwned::to_owned),
+                    duration: track["duration"].as_f64(),
+                    ..PlaylistEntry::default()
+                });
+            }
+            match next_page(&json, &page_url) {
+                Some(next) if seen.insert(next.clone()) => page_url = next,
+                _ => return Ok(entries),
+            }
+        }
     }
 }
 
@@ -230,27 +698,99 @@ impl Parser for WhypParser {
     fn extension(&self) -> &str {
         &self.extension
     }
+
+    fn uploader(&self) -> Option<&str> {
+        self.uploader.as_deref()
+    }
+
+    fn metadata(&self) -> Option<&Metadata> {
+        Some(&self.metadata)
+    }
+
+    fn name() -> &'static str {
+        "Whyp"
+    }
+
+    fn hosts() -> &'static [&'static str] {
+        &["whyp.it"]
+    }
+
+    fn examples() -> &'static [&'static str] {
+        &[
+            "https://whyp.it/tracks/<id>/<slug>",
+            "https://whyp.it/users/<id>/<slug>",
+            "https://whyp.it/playlists/<id>/<slug>",
+        ]
+    }
+
+    async fn from_url(url: &Url) -> Result<Self, String> {
+        Self::new(url.as_str()).await
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            has_title: true,
+            supports_playlists: true,
+            ..Capabilities::default()
+        }
+    }
+
+    async fn expand(url: &Url) -> Result<Option<Vec<PlaylistEntry>>, String> {
+        match path_segments(url).as_slice() {
+            ["users", id, ..] => Ok(Some(Self::parse_tracks(url, "users", id).await?)),
+            ["playlists", id, ..] => Ok(Some(Self::parse_tracks(url, "playlists", id).await?)),
+            _ => Ok(None),
+        }
+    }
 }
 
 pub struct ErocastParser {
     audio: String,
     title: String,
     chunks: Vec<String>,
+    uploader: Option<String>,
+    metadata: Metadata,
 }
 
 impl ErocastParser {
     const CHUNK_REGEX: &str = r"https:\/\/erocast.s3.us-east-2.wasabisys.com\/\d+\/\w+\.ts";
 
     pub async fn new(url: &str) -> Result<Self, String> {
-        let (audio, title, chunks) = Self::parse_info(url).await?;
+        let (audio, title, chunks, uploader, metadata) = Self::parse_info(url).await?;
         Ok(Self {
             audio,
             title,
             chunks,
+            uploader,
+            metadata,
         })
     }
 
-    async fn parse_info(url: &str) -> Result<(String, String, Vec<String>), String> {
+    async fn parse_info(url: &str) -> Result<ChunkedInfo, String> {
+        let json = Self::fetch_json(url).await?;
+        let audio = json["file_url"]
+            .as_str()
+            .ok_or("File URL is missing from JSON response")?
+            .to_owned();
+        let title = json["title"]
+            .as_str()
+            .ok_or("Title is missing from JSON response")?
+            .to_owned();
+        let chunks = chunks_from_playlist(&audio, Self::CHUNK_REGEX).await?;
+        let uploader = Self::display_name(&json["user"]);
+        let metadata =
+            Metadata::from_json(&json, "description", "created_at", "tags", "artwork_url");
+        Ok((audio, title, chunks, uploader, metadata))
+    }
+
+    fn display_name(user: &Value) -> Option<String> {
+        user["name"]
+            .as_str()
+            .or(user["username"].as_str())
+            .map(ToOwned::to_owned)
+    }
+
+    async fn fetch_json(url: &str) -> Result<Value, String> {
         let client = Client::new();
         let mut headers = HeaderMap::new();
         headers.insert(
@@ -274,17 +814,47 @@ impl ErocastParser {
             .text()
             .await
             .map_err(|e| e.to_string())?;
-        let json: Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
-        let audio = json["file_url"]
-            .as_str()
-            .ok_or("File URL is missing from JSON response")?
-            .to_owned();
-        let title = json["title"]
-            .as_str()
-            .ok_or("Title is missing from JSON response")?
-            .to_owned();
-        let chunks = chunks_from_playlist(&audio, Self::CHUNK_REGEX).await?;
-        Ok((audio, title, chunks))
+        serde_json::from_str(&body).map_err(|e| e.to_string())
+    }
+
+    async fn parse_profile(url: &Url) -> Result<Vec<PlaylistEntry>, String> {
+        let mut url = url.clone();
+        let mut seen = HashSet::from([url.clone()]);
+        let mut entries = Vec::new();
+        loop {
+            let json = Self::fetch_json(url.as_str()).await?;
+            let page = &json["songs"];
+            let songs = page["data"]
+                .as_array()
+                .or(page.as_array())
+                .ok_or("Songs are missing from JSON response")?;
+            for song in songs {
+                let song_url = match song["permalink_url"].as_str() {
+                    Some(permalink) => permalink.to_owned(),
+                    None => {
+                        let id = song["id"]
+                            .as_u64()
+                            .ok_or("Song id is missing from JSON response")?;
+                        let slug = song["slug"]
+                            .as_str()
+                            .ok_or("Song slug is missing from JSON response")?;
+                        format!("https://erocast.me/track/{id}/{slug}")
+                    }
+                };
+                entries.push(PlaylistEntry {
+                    url: song_url,
+                    op: Self::display_name(&song["user"])
+                        .or_else(|| Self::display_name(&json["user"])),
+                    title: song["title"].as_str().map(ToOwned::to_owned),
+                    duration: song["duration"].as_f64(),
+                    ..PlaylistEntry::default()
+                });
+            }
+            match next_page(page, &url) {
+                Some(next) if seen.insert(next.clone()) => url = next,
+                _ => return Ok(entries),
+            }
+        }
     }
 }
 
@@ -304,13 +874,208 @@ impl Parser for ErocastParser {
     fn chunks(&self) -> Option<&[String]> {
         Some(self.chunks.as_slice())
     }
+
+    fn uploader(&self) -> Option<&str> {
+        self.uploader.as_deref()
+    }
+
+    fn metadata(&self) -> Option<&Metadata> {
+        Some(&self.metadata)
+    }
+
+    fn name() -> &'static str {
+        "Erocast"
+    }
+
+    fn hosts() -> &'static [&'static str] {
+        &["erocast.me"]
+    }
+
+    fn examples() -> &'static [&'static str] {
+        &[
+            "https://erocast.me/track/<id>/<slug>",
+            "https://erocast.me/<user>",
+        ]
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            has_title: true,
+            provides_chunks: true,
+            supports_playlists: true,
+            ..Capabilities::default()
+        }
+    }
+
+    async fn expand(url: &Url) -> Result<Option<Vec<PlaylistEntry>>, String> {
+        match path_segments(url).as_slice() {
+            [_] => Ok(Some(Self::parse_profile(url).await?)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn from_url(url: &Url) -> Result<Self, String> {
+        Self::new(url.as_str()).await
+    }
+}
+
+// Reddit posts only link to audio hosted elsewhere, so a post always expands
+// into entries for the supported links it contains and is never downloaded.
+pub struct RedditParser;
+
+impl RedditParser {
+    async fn parse_post(sub: &str, id: &str) -> Result<Vec<PlaylistEntry>, String> {
+        let url = format!("https://www.reddit.com/r/{sub}/comments/{id}.json?raw_json=1");
+        let body = Client::new()
+            .get(&url)
+            .header(USER_AGENT, "rad/0.2.0")
+            .send()
+            .await
+            .map_err(|e| format!("Failed to fetch post {url}: {e}"))?
+            .error_for_status()
+            .map_err(|e| format!("Failed to fetch post {url}: {e}"))?
+            .text()
+            .await
+            .map_err(|e| e.to_string())?;
+        let json: Value = serde_json::from_str(&body).map_err(|e| e.to_string())?;
+        let post = &json[0]["data"]["children"][0]["data"];
+        let author = post["author"]
+            .as_str()
+            .ok_or("Author is missing from post JSON")?;
+        let subreddit = post["subreddit"]
+            .as_str()
+            .ok_or("Subreddit is missing from post JSON")?;
+        let mut texts = vec![
+            post["url"].as_str().unwrap_or_default(),
+            post["title"].as_str().unwrap_or_default(),
+            post["selftext"].as_str().unwrap_or_default(),
+        ];
+        Self::comments_by(&json[1], author, &mut texts);
+        let entries: Vec<PlaylistEntry> = extract_links(&texts.join("\n"))?
+            .into_iter()
+            .filter(|link| find_parser(link).is_some_and(|parser| parser.name != Self::name()))
+            .map(|link| PlaylistEntry {
+                url: link.into(),
+                op: Some(author.to_owned()),
+                sub: Some(subreddit.to_owned()),
+                ..PlaylistEntry::default()
+            })
+            .collect();
+        if entries.is_empty() {
+            return Err(format!("Post contains no supported links: {url}"));
+        }
+        Ok(entries)
+    }
+
+    fn comments_by<'a>(listing: &'a Value, author: &str, texts: &mut Vec<&'a str>) {
+        let Some(children) = listing["data"]["children"].as_array() else {
+            return;
+        };
+        for comment in children {
+            let data = &comment["data"];
+            if data["author"].as_str() == Some(author)
+                && let Some(body) = data["body"].as_str()
+            {
+                texts.push(body);
+            }
+            Self::comments_by(&data["replies"], author, texts);
+        }
+    }
+}
+
+impl Parser for RedditParser {
+    fn audio(&self) -> &'static str {
+        ""
+    }
+
+    fn title(&self) -> &'static str {
+        ""
+    }
+
+    fn extension(&self) -> &'static str {
+        ""
+    }
+
+    fn name() -> &'static str {
+        "Reddit"
+    }
+
+    fn hosts() -> &'static [&'static str] {
+        &["reddit.com"]
+    }
+
+    fn examples() -> &'static [&'static str] {
+        &["https://www.reddit.com/r/<subreddit>/comments/<id>/<slug>"]
+    }
+
+    fn capabilities() -> Capabilities {
+        Capabilities {
+            supports_playlists: true,
+            ..Capabilities::default()
+        }
+    }
+
+    async fn from_url(url: &Url) -> Result<Self, String> {
+        Err(format!("Reddit URL is not a post: {url}"))
+    }
+
+    async fn expand(url: &Url) -> Result<Option<Vec<PlaylistEntry>>, String> {
+        match path_segments(url).as_slice() {
+            ["r", sub, "comments", id, ..] => Ok(Some(Self::parse_post(sub, id).await?)),
+            _ => Ok(None),
+        }
+    }
+}
+
+// Paginated APIs either link the next page or report the current and last
+// page numbers, in which case the next page is requested by number.
+fn next_page(page: &Value, url: &Url) -> Option<Url> {
+    if let Some(link) = page["next_page_url"]
+        .as_str()
+        .or(page["links"]["next"].as_str())
+    {
+        return url.join(link).ok();
+    }
+    let meta = if page["meta"].is_object() {
+        &page["meta"]
+    } else {
+        page
+    };
+    let current = meta["current_page"]
+        .as_u64()
+        .or(meta["currentPage"].as_u64())?;
+    let last = meta["last_page"]
+        .as_u64()
+        .or(meta["lastPage"].as_u64())
+        .or(meta["totalPages"].as_u64())?;
+    if current >= last {
+        return None;
+    }
+    let query: Vec<(String, String)> = url
+        .query_pairs()
+        .filter(|(key, _)| key != "page")
+        .map(|(key, value)| (key.into_owned(), value.into_owned()))
+        .collect();
+    let mut next = url.clone();
+    next.query_pairs_mut()
+        .clear()
+        .extend_pairs(query)
+        .append_pair("page", &(current + 1).to_string());
+    Some(next)
+}
+
+fn path_segments(url: &Url) -> Vec<&str> {
+    url.path_segments()
+        .map(|segments| segments.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default()
 }
 
 async fn info_from_page(
     url: &str,
     audio_regex: &str,
     title_selector: &str,
-) -> Result<(String, String, String), String> {
+    description_selector: &str,
+) -> Result<(String, String, String, Metadata), String> {
     let response = reqwest::get(url)
         .await
         .map_err(|e| format!("Failed to fetch page {url}: {e}"))?;
@@ -348,12 +1113,26 @@ async fn info_from_page(
         })?
         .text()
         .collect();
-    let title = Regex::new(r"(\[.+?\])")
-        .map_err(|e| e.to_string())?
-        .replace_all(&raw_title, "")
-        .trim()
-        .to_owned();
-    Ok((audio, title, extension))
+    // Bracketed parts of the title are the tags used on the page.
+    let tag_regex = Regex::new(r"\[(.+?)\]").map_err(|e| e.to_string())?;
+    let keywords = tag_regex
+        .captures_iter(&raw_title)
+        .filter_map(|caps| caps.get(1))
+        .map(|m| m.as_str().trim().to_owned())
+        .collect();
+    let title = tag_regex.replace_all(&raw_title, "").trim().to_owned();
+    let description_selector = Selector::parse(description_selector).map_err(|e| e.to_string())?;
+    let description = document
+        .select(&description_selector)
+        .next()
+        .map(|element| element.text().collect::<String>().trim().to_owned())
+        .filter(|text| !text.is_empty());
+    let metadata = Metadata {
+        description,
+        keywords,
+        ..Metadata::default()
+    };
+    Ok((audio, title, extension, metadata))
 }
 
 async fn chunks_from_playlist(url: &str, chunk_regex: &str) -> Result<Vec<String>, String> {
@@ -0,0 +1,89 @@
+use std::time::{Duration, Instant};
+use tauri::{AppHandle, Emitter, Runtime};
+
+#[derive(Default, Debug, Clone, serde::Serialize)]
+pub struct DownloadProgress {
+    id: usize,
+    received: u64,
+    total: Option<u64>,
+    chunks_received: usize,
+    chunks_total: usize,
+    rate: u64,
+}
+
+pub struct ProgressTracker<'a, R: Runtime> {
+    app_handle: &'a AppHandle<R>,
+    progress: DownloadProgress,
+    last_emit: Instant,
+    last_received: u64,
+}
+
+impl<'a, R: Runtime> ProgressTracker<'a, R> {
+    const EMIT_INTERVAL: Duration = Duration::from_millis(250);
+
+    pub fn new(id: usize, app_handle: &'a AppHandle<R>) -> Self {
+        Self {
+            app_handle,
+            progress: DownloadProgress {
+                id,
+                ..DownloadProgress::default()
+            },
+            last_emit: Instant::now(),
+            last_received: 0,
+        }
+    }
+
+    pub fn resume_from(&mut self, received: u64, chunks_received: usize) {
+        self.progress.received = received;
+        self.progress.chunks_received = chunks_received;
+        self.last_received = received;
+    }
+
+    pub fn set_total(&mut self, total: Option<u64>) {
+        self.progress.total = total;
+    }
+
+    pub fn set_chunks_total(&mut self, chunks_total: usize) {
+        self.progress.chunks_total = chunks_total;
+    }
+
+    pub fn advance(&mut self, bytes: usize) -> Result<(), String> {
+        self.progress.received += bytes as u64;
+        self.emit_throttled()
+    }
+
+    // Segment sizes are unknown up front, so the total is extrapolated from the
+    // average size of the segments received so far.
+    pub fn advance_chunk(&mut self, bytes: usize) -> Result<(), String> {
+        self.progress.received += bytes as u64;
+        self.progress.chunks_received += 1;
+        let average = self.progress.received / self.progress.chunks_received as u64;
+        self.progress.total = Some(average * self.progress.chunks_total as u64);
+        self.emit_throttled()
+    }
+
+    pub fn finish(&mut self) -> Result<(), String> {
+        self.progress.total = Some(self.progress.received);
+        self.emit()
+    }
+
+    fn emit_throttled(&mut self) -> Result<(), String> {
+        if self.last_emit.elapsed() < Self::EMIT_INTERVAL {
+            return Ok(());
+        }
+        self.emit()
+    }
+
+    fn emit(&mut self) -> Result<(), String> {
+        let elapsed = self.last_emit.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            let bytes = self.progress.received - self.last_received;
+            self.progress.rate = (bytes as f64 / elapsed) as u64;
+        }
+        self.last_emit = Instant::now();
+        self.last_received = self.progress.received;
+        self.app_handle
+            .emit::<DownloadProgress>("download_progress", self.progress.clone())
+            .map_err(|e| e.to_string())
+    }
+}
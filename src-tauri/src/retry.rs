@@ -0,0 +1,97 @@
+use reqwest::StatusCode;
+use std::{
+    hash::{BuildHasher, RandomState},
+    time::Duration,
+};
+use tokio::time::sleep;
+
+// A failed download attempt, which is only retried when trying again could
+// succeed. Plain string errors, such as failing to write to disk, are final.
+#[derive(Debug)]
+pub struct AttemptError {
+    message: String,
+    transient: bool,
+}
+
+impl AttemptError {
+    // Connection problems, timeouts, interrupted bodies, server errors and rate
+    // limiting can clear up on their own, while other HTTP errors will not.
+    pub fn request(context: &str, e: reqwest::Error) -> Self {
+        let transient = match e.status() {
+            Some(status) => status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS,
+            None => e.is_connect() || e.is_timeout() || e.is_request() || e.is_body(),
+        };
+        Self {
+            message: format!("{context}: {e}"),
+            transient,
+        }
+    }
+
+    pub fn is_transient(&self) -> bool {
+        self.transient
+    }
+}
+
+impl From<String> for AttemptError {
+    fn from(message: String) -> Self {
+        Self {
+            message,
+            transient: false,
+        }
+    }
+}
+
+impl From<AttemptError> for String {
+    fn from(error: AttemptError) -> Self {
+        error.message
+    }
+}
+
+#[derive(Debug, Clone, Copy, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct RetryPolicy {
+    retries: u32,
+    base_delay_ms: u64,
+    max_delay_ms: u64,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 3,
+            base_delay_ms: 1000,
+            max_delay_ms: 30_000,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.base_delay_ms > self.max_delay_ms {
+            return Err("Retry base delay must not exceed the maximum delay".to_owned());
+        }
+        Ok(())
+    }
+
+    // Waits before the next attempt and returns true, or returns false once
+    // all retries have been used up.
+    pub async fn backoff(&self, attempt: &mut u32) -> bool {
+        if *attempt >= self.retries {
+            return false;
+        }
+        sleep(self.delay(*attempt)).await;
+        *attempt += 1;
+        true
+    }
+
+    // Exponential backoff with "full jitter": a random delay between zero and
+    // the capped exponential bound, so concurrent segments do not retry in step.
+    fn delay(&self, attempt: u32) -> Duration {
+        let bound = self
+            .base_delay_ms
+            .saturating_mul(2_u64.saturating_pow(attempt))
+            .min(self.max_delay_ms);
+        let jitter = RandomState::new().hash_one(attempt) % (bound + 1);
+        Duration::from_millis(jitter)
+    }
+}
@@ -0,0 +1,117 @@
+// Most filesystems limit a single path component to 255 bytes.
+pub const MAX_FILENAME_BYTES: usize = 255;
+
+const FORBIDDEN: [char; 9] = ['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+const RESERVED: [&str; 4] = ["CON", "PRN", "AUX", "NUL"];
+
+// Removes characters that are invalid in a path component on any supported
+// platform, including path separators and control characters.
+pub fn strip_invalid(value: &str) -> String {
+    value
+        .chars()
+        .filter(|c| !FORBIDDEN.contains(c) && !c.is_control())
+        .collect()
+}
+
+// Produces a single path component that is valid on Windows, macOS and Linux,
+// or an empty string when nothing usable is left.
+pub fn sanitize_segment(segment: &str) -> String {
+    let stripped = strip_invalid(segment);
+    let mut segment = stripped.trim().trim_end_matches(['.', ' ']).to_owned();
+    if segment.chars().all(|c| c == '.') {
+        return String::new();
+    }
+    if is_reserved(&segment) {
+        let stem_len = segment.find('.').unwrap_or(segment.len());
+        segment.insert(stem_len, '_');
+    }
+    segment
+}
+
+fn is_reserved(segment: &str) -> bool {
+    let stem = segment.split('.').next().unwrap_or_default().trim_end();
+    let upper = stem.to_ascii_uppercase();
+    RESERVED.contains(&upper.as_str())
+        || ["COM", "LPT"].iter().any(|prefix| {
+            upper
+                .strip_prefix(prefix)
+                .is_some_and(|n| n.len() == 1 && n.as_bytes()[0].is_ascii_digit())
+        })
+}
+
+// Cuts `value` to at most `max_bytes` without splitting a UTF-8 character.
+pub fn truncate_bytes(value: &str, max_bytes: usize) -> &str {
+    if value.len() <= max_bytes {
+        return value;
+    }
+    let mut end = max_bytes;
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    &value[..end]
+}
+
+// Joins a stem and extension into a component of at most `max_bytes`,
+// shortening the stem so the extension is never cut off.
+pub fn with_extension(stem: &str, extension: &str, max_bytes: usize) -> String {
+    let suffix = if extension.is_empty() {
+        String::new()
+    } else {
+        format!(".{extension}")
+    };
+    let stem = truncate_bytes(stem, max_bytes.saturating_sub(suffix.len()));
+    let stem = stem.trim_end_matches(['.', ' ']);
+    format!("{stem}{suffix}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strips_forbidden_and_control_characters() {
+        assert_eq!(strip_invalid("a<b>c:d\"e/f\\g|h?i*j"), "abcdefghij");
+        assert_eq!(strip_invalid("tab\there\nnew\u{7f}line"), "tabherenewline");
+    }
+
+    #[test]
+    fn trims_whitespace_and_trailing_dots() {
+        assert_eq!(sanitize_segment("  title. . "), "title");
+        assert_eq!(sanitize_segment("..."), "");
+        assert_eq!(sanitize_segment(".."), "");
+        assert_eq!(sanitize_segment(" .hidden"), ".hidden");
+    }
+
+    #[test]
+    fn escapes_windows_reserved_names() {
+        assert_eq!(sanitize_segment("CON"), "CON_");
+        assert_eq!(sanitize_segment("con.mp3"), "con_.mp3");
+        assert_eq!(sanitize_segment("Lpt1"), "Lpt1_");
+        assert_eq!(sanitize_segment("COM10"), "COM10");
+        assert_eq!(sanitize_segment("Console"), "Console");
+    }
+
+    #[test]
+    fn truncates_on_char_boundaries() {
+        assert_eq!(truncate_bytes("short", 10), "short");
+        assert_eq!(truncate_bytes("abcdef", 3), "abc");
+        // "é" is two bytes, so cutting through it keeps only the first char.
+        assert_eq!(truncate_bytes("aé", 2), "a");
+        assert_eq!(truncate_bytes("🎧🎧", 5), "🎧");
+    }
+
+    #[test]
+    fn preserves_extension_when_truncating() {
+        let stem = "ä".repeat(200);
+        let name = with_extension(&stem, "mp3", MAX_FILENAME_BYTES);
+        assert!(name.len() <= MAX_FILENAME_BYTES);
+        assert!(name.ends_with(".mp3"));
+        assert_eq!(
+            with_extension("title", "m4a", MAX_FILENAME_BYTES),
+            "title.m4a"
+        );
+        assert_eq!(with_extension("abc. def", "mp3", 9), "abc.mp3");
+        assert_eq!(with_extension("title", "", 3), "tit");
+    }
+}
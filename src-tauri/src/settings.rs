@@ -0,0 +1,74 @@
+use crate::file::CollisionPolicy;
+use crate::retry::RetryPolicy;
+use crate::tags::TagSettings;
+use crate::template::FilenameTemplate;
+use crate::transcode::TranscodeSettings;
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct Settings {
+    max_downloads: usize,
+    max_downloads_per_host: usize,
+    retry: RetryPolicy,
+    filename_template: FilenameTemplate,
+    collision: CollisionPolicy,
+    tags: TagSettings,
+    transcode: TranscodeSettings,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            max_downloads: 3,
+            max_downloads_per_host: 2,
+            retry: RetryPolicy::default(),
+            filename_template: FilenameTemplate::default(),
+            collision: CollisionPolicy::default(),
+            tags: TagSettings::default(),
+            transcode: TranscodeSettings::default(),
+        }
+    }
+}
+
+impl Settings {
+    pub fn max_downloads(&self) -> usize {
+        self.max_downloads
+    }
+
+    pub fn max_downloads_per_host(&self) -> usize {
+        self.max_downloads_per_host
+    }
+
+    pub fn retry(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    pub fn filename_template(&self) -> &FilenameTemplate {
+        &self.filename_template
+    }
+
+    pub fn collision(&self) -> CollisionPolicy {
+        self.collision
+    }
+
+    pub fn tags(&self) -> &TagSettings {
+        &self.tags
+    }
+
+    pub fn transcode(&self) -> &TranscodeSettings {
+        &self.transcode
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        if self.max_downloads == 0 {
+            return Err("Maximum concurrent downloads must be at least 1".to_owned());
+        }
+        if self.max_downloads_per_host == 0 {
+            return Err("Maximum concurrent downloads per host must be at least 1".to_owned());
+        }
+        self.retry.validate()?;
+        self.filename_template.validate()?;
+        self.tags.validate()?;
+        self.transcode.validate()
+    }
+}
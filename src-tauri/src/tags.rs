@@ -0,0 +1,177 @@
+use crate::downloads::DownloadItem;
+use crate::template::Template;
+use bytes::Bytes;
+use lofty::{
+    Accessor, ItemKey, ItemValue, Picture, PictureType, Probe, Tag, TagExt, TagItem, TagType,
+    TaggedFileExt,
+};
+use reqwest::Client;
+use std::{collections::BTreeMap, path::Path};
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, serde::Deserialize, serde::Serialize,
+)]
+pub enum TagField {
+    Title,
+    Artist,
+    Album,
+    AlbumArtist,
+    Genre,
+    Comment,
+    Date,
+    Keywords,
+    Source,
+}
+
+impl TagField {
+    // Vorbis comments have no standard keys for keywords and source URLs, so
+    // the common KEYWORDS and SOURCE field names are used instead. MP4 has no
+    // source URL atom either and uses an iTunes freeform atom.
+    fn item_key(self, tag_type: TagType) -> ItemKey {
+        let vorbis = tag_type == TagType::VorbisComments;
+        match self {
+            Self::Title => ItemKey::TrackTitle,
+            Self::Artist => ItemKey::TrackArtist,
+            Self::Album => ItemKey::AlbumTitle,
+            Self::AlbumArtist => ItemKey::AlbumArtist,
+            Self::Genre => ItemKey::Genre,
+            Self::Comment => ItemKey::Comment,
+            Self::Date => ItemKey::RecordingDate,
+            Self::Keywords if vorbis => ItemKey::Unknown("KEYWORDS".to_owned()),
+            Self::Keywords => ItemKey::PodcastKeywords,
+            Self::Source if vorbis => ItemKey::Unknown("SOURCE".to_owned()),
+            Self::Source if tag_type == TagType::Mp4Ilst => {
+                ItemKey::Unknown("----:com.apple.iTunes:SOURCE".to_owned())
+            }
+            Self::Source => ItemKey::AudioSourceUrl,
+        }
+    }
+
+    // ID3v2 only accepts URL values in its `W` frames, so the source URL has
+    // to be a locator there rather than text.
+    fn item(self, tag_type: TagType, value: String) -> TagItem {
+        let value = if self == Self::Source && tag_type == TagType::Id3v2 {
+            ItemValue::Locator(value)
+        } else {
+            ItemValue::Text(value)
+        };
+        TagItem::new(self.item_key(tag_type), value)
+    }
+}
+
+// Each tag field is rendered from a template over the item's fields, and
+// fields that are missing from the mapping or render empty are not written.
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct TagSettings {
+    mapping: BTreeMap<TagField, Template>,
+    disabled_hosts: Vec<String>,
+    disabled_extensions: Vec<String>,
+}
+
+impl Default for TagSettings {
+    fn default() -> Self {
+        let mapping = [
+            (TagField::Title, "{title}"),
+            (TagField::Artist, "{op}"),
+            (TagField::Album, "{op}"),
+            (TagField::AlbumArtist, "{op}"),
+            (TagField::Genre, "{sub}"),
+            (TagField::Comment, "{description}"),
+            (TagField::Date, "{uploaded}"),
+            (TagField::Keywords, "{keywords}"),
+            (TagField::Source, "{url}"),
+        ]
+        .into_iter()
+        .map(|(field, template)| (field, Template::new(template)))
+        .collect();
+        Self {
+            mapping,
+            disabled_hosts: Vec::new(),
+            disabled_extensions: Vec::new(),
+        }
+    }
+}
+
+impl TagSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        self.mapping.iter().try_for_each(|(field, template)| {
+            template
+                .validate()
+                .map_err(|e| format!("Invalid template for {field:?} tag: {e}"))
+        })
+    }
+
+    pub fn is_enabled(&self, download: &DownloadItem, extension: &str) -> bool {
+        !self
+            .disabled_hosts
+            .iter()
+            .any(|disabled| download.is_from_host(disabled))
+            && !self.disabled_extensions.iter().any(|disabled| {
+                disabled
+                    .trim()
+                    .trim_start_matches('.')
+                    .eq_ignore_ascii_case(extension)
+            })
+    }
+}
+
+// Cover art is optional, so a missing or broken thumbnail never fails the
+// download.
+pub async fn fetch_cover(client: &Client, thumbnail: Option<&str>) -> Option<Bytes> {
+    let response = client.get(thumbnail?).send().await.ok()?;
+    response.error_for_status().ok()?.bytes().await.ok()
+}
+
+pub fn tag_file(
+    file_path: &Path,
+    download: &DownloadItem,
+    extension: &str,
+    settings: &TagSettings,
+    cover: Option<&[u8]>,
+) -> Result<(), String> {
+    if !settings.is_enabled(download, extension) {
+        return Ok(());
+    }
+    // Finished files still carry a partial suffix, so the format is detected
+    // from the contents rather than the extension.
+    let tagged_file = Probe::open(file_path)
+        .map_err(|e| e.to_string())
+        .and_then(|probe| probe.guess_file_type().map_err(|e| e.to_string()))
+        .and_then(|probe| probe.read().map_err(|e| e.to_string()))
+        .map_err(|e| format!("Failed to read tags from file: {e}"))?;
+    let tag_type = tagged_file.primary_tag_type();
+    let mut tag = Tag::new(tag_type);
+    for (field, template) in &settings.mapping {
+        let value = template.render(|name| download.template_value(name, extension))?;
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        let item = field.item(tag_type, value.to_owned());
+        // Custom keys have no mapping to check against, so they are written
+        // as they are.
+        if matches!(item.key(), ItemKey::Unknown(_)) {
+            tag.insert_unchecked(item);
+        } else if !tag.insert(item) {
+            return Err(format!(
+                "{field:?} tag cannot be written to {tag_type:?} tags, remove it from the \
+                 tag mapping or disable tagging for .{extension} files"
+            ));
+        }
+        if *field == TagField::Date
+            && let Some(year) = value.get(..4).and_then(|year| year.parse().ok())
+        {
+            tag.set_year(year);
+        }
+    }
+    if let Some(track) = download.track() {
+        tag.set_track(track);
+    }
+    if let Some(mut picture) = cover.and_then(|data| Picture::from_reader(&mut &data[..]).ok()) {
+        picture.set_pic_type(PictureType::CoverFront);
+        tag.push_picture(picture);
+    }
+    tag.save_to_path(file_path)
+        .map_err(|e| format!("Failed to write tags to file: {e}"))
+}
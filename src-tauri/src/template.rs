@@ -0,0 +1,183 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Placeholders are written as `{name}`, and text wrapped in `<...>` is only
+// kept when every placeholder inside it has a value, so `<[{sub}] >` drops the
+// brackets entirely for items without a category.
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(transparent)]
+pub struct Template(String);
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(transparent)]
+pub struct FilenameTemplate(Template);
+
+impl Default for FilenameTemplate {
+    fn default() -> Self {
+        Self(Template::new("<[{sub}] ><[{op}] >{title}"))
+    }
+}
+
+impl FilenameTemplate {
+    // A filename made only of literal text would give every item the same
+    // name, so at least one placeholder is required.
+    pub fn validate(&self) -> Result<(), String> {
+        if !self.0.parse()?.iter().any(Segment::has_field) {
+            return Err("Filename template must contain at least one placeholder".to_owned());
+        }
+        Ok(())
+    }
+
+    pub fn render(&self, value: impl Fn(&str) -> String) -> Result<String, String> {
+        self.0.render(value)
+    }
+}
+
+#[derive(Debug)]
+enum Segment<'a> {
+    Text(&'a str),
+    Field(&'a str),
+    Section(Vec<Segment<'a>>),
+}
+
+impl Segment<'_> {
+    fn has_field(&self) -> bool {
+        match self {
+            Self::Text(_) => false,
+            Self::Field(_) => true,
+            Self::Section(segments) => segments.iter().any(Self::has_field),
+        }
+    }
+}
+
+impl Template {
+    pub const FIELDS: [&str; 12] = [
+        "op",
+        "sub",
+        "title",
+        "host",
+        "id",
+        "date",
+        "ext",
+        "url",
+        "track",
+        "description",
+        "keywords",
+        "uploaded",
+    ];
+
+    pub fn new(template: &str) -> Self {
+        Self(template.to_owned())
+    }
+
+    pub fn validate(&self) -> Result<(), String> {
+        self.parse().map(|_| ())
+    }
+
+    pub fn render(&self, value: impl Fn(&str) -> String) -> Result<String, String> {
+        let mut rendered = String::new();
+        for segment in self.parse()? {
+            match segment {
+                Segment::Text(text) => rendered.push_str(text),
+                Segment::Field(name) => rendered.push_str(&value(name)),
+                Segment::Section(segments) => {
+                    let mut section = String::new();
+                    let mut complete = true;
+                    for segment in segments {
+                        match segment {
+                            Segment::Text(text) => section.push_str(text),
+                            Segment::Field(name) => {
+                                let field = value(name);
+                                complete &= !field.trim().is_empty();
+                                section.push_str(&field);
+                            }
+                            Segment::Section(_) => unreachable!("sections are not nested"),
+                        }
+                    }
+                    if complete {
+                        rendered.push_str(&section);
+                    }
+                }
+            }
+        }
+        Ok(rendered)
+    }
+
+    fn parse(&self) -> Result<Vec<Segment<'_>>, String> {
+        let template = self.0.as_str();
+        let mut segments = Vec::new();
+        let mut section: Option<Vec<Segment>> = None;
+        let mut rest = template;
+        while let Some(i) = rest.find(['{', '}', '<', '>']) {
+            let (text, tail) = rest.split_at(i);
+            if !text.is_empty() {
+                section
+                    .as_mut()
+                    .unwrap_or(&mut segments)
+                    .push(Segment::Text(text));
+            }
+            rest = &tail[1..];
+            match tail.as_bytes()[0] {
+                b'{' => {
+                    let end = rest
+                        .find('}')
+                        .ok_or(format!("Unclosed placeholder in template: {template}"))?;
+                    let name = &rest[..end];
+                    if !Self::FIELDS.contains(&name) {
+                        return Err(format!(
+                            "Unknown placeholder {{{name}}}, expected one of: {}",
+                            Self::FIELDS.map(|f| format!("{{{f}}}")).join(", ")
+                        ));
+                    }
+                    section
+                        .as_mut()
+                        .unwrap_or(&mut segments)
+                        .push(Segment::Field(name));
+                    rest = &rest[end + 1..];
+                }
+                b'<' if section.is_some() => {
+                    return Err(format!("Sections cannot be nested: {template}"));
+                }
+                b'<' => section = Some(Vec::new()),
+                b'>' => {
+                    let inner = section
+                        .take()
+                        .ok_or(format!("Unopened section in template: {template}"))?;
+                    segments.push(Segment::Section(inner));
+                }
+                _ => return Err(format!("Unopened placeholder in template: {template}")),
+            }
+        }
+        if section.is_some() {
+            return Err(format!("Unclosed section in template: {template}"));
+        }
+        if !rest.is_empty() {
+            segments.push(Segment::Text(rest));
+        }
+        Ok(segments)
+    }
+}
+
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// Formats a unix timestamp as a UTC `YYYY-MM-DD` date.
+pub fn format_date(timestamp: u64) -> String {
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
@@ -0,0 +1,145 @@
+use crate::downloads::DownloadItem;
+use ffmpeg_sidecar::{self, child::FfmpegChild, command::FfmpegCommand};
+use std::{collections::BTreeMap, io, path::Path, time::Duration};
+use tokio::{task::spawn_blocking, time::sleep};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "format")]
+pub enum TranscodeFormat {
+    Mp3 { bitrate: u32 },
+    Opus { bitrate: u32 },
+    Flac,
+    // Copies the audio stream into an MP4 container without re-encoding.
+    M4a,
+}
+
+impl TranscodeFormat {
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Mp3 { .. } => "mp3",
+            Self::Opus { .. } => "opus",
+            Self::Flac => "flac",
+            Self::M4a => "m4a",
+        }
+    }
+
+    fn validate(self) -> Result<(), String> {
+        let (name, bitrate, range) = match self {
+            Self::Mp3 { bitrate } => ("MP3", bitrate, 32..=320),
+            Self::Opus { bitrate } => ("Opus", bitrate, 6..=510),
+            Self::Flac | Self::M4a => return Ok(()),
+        };
+        if !range.contains(&bitrate) {
+            return Err(format!(
+                "{name} bitrate must be between {} and {} kbps",
+                range.start(),
+                range.end()
+            ));
+        }
+        Ok(())
+    }
+
+    // Bitrate targets always re-encode, but converting a file to the lossless
+    // format or container it already has would only make a copy of it.
+    fn applies_to(self, extension: &str) -> bool {
+        match self {
+            Self::Mp3 { .. } | Self::Opus { .. } => true,
+            Self::Flac | Self::M4a => !extension.eq_ignore_ascii_case(self.extension()),
+        }
+    }
+}
+
+// Hosts are matched like the tag settings' disabled hosts, and a host mapped
+// to null is never transcoded even when a global format is set.
+#[derive(Default, Debug, Clone, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct TranscodeSettings {
+    format: Option<TranscodeFormat>,
+    hosts: BTreeMap<String, Option<TranscodeFormat>>,
+    keep_original: bool,
+}
+
+impl TranscodeSettings {
+    pub fn validate(&self) -> Result<(), String> {
+        self.format.map_or(Ok(()), TranscodeFormat::validate)?;
+        self.hosts.iter().try_for_each(|(host, format)| {
+            format
+                .map_or(Ok(()), TranscodeFormat::validate)
+                .map_err(|e| format!("Invalid transcoding for {host}: {e}"))
+        })
+    }
+
+    pub fn keep_original(&self) -> bool {
+        self.keep_original
+    }
+
+    pub fn format_for(&self, download: &DownloadItem) -> Option<TranscodeFormat> {
+        self.hosts
+            .iter()
+            .find(|(host, _)| download.is_from_host(host))
+            .map_or(self.format, |(_, format)| *format)
+            .filter(|format| format.applies_to(download.extension()))
+    }
+}
+
+// Kills the ffmpeg process when dropped, so aborting the download task also
+// stops any conversion in progress.
+struct FfmpegProcess(FfmpegChild);
+
+impl Drop for FfmpegProcess {
+    fn drop(&mut self) {
+        self.0.kill().ok();
+        self.0.as_inner_mut().try_wait().ok();
+    }
+}
+
+pub async fn ts_to_mp3(input: &Path, output: &Path) -> Result<(), String> {
+    run(FfmpegCommand::new()
+        .input(input.to_string_lossy())
+        .codec_audio("copy")
+        .no_video()
+        .format("mp3")
+        .overwrite()
+        .output(output.to_string_lossy()))
+    .await
+}
+
+pub async fn transcode(input: &Path, output: &Path, format: TranscodeFormat) -> Result<(), String> {
+    let mut command = FfmpegCommand::new();
+    command.input(input.to_string_lossy()).no_video();
+    match format {
+        TranscodeFormat::Mp3 { bitrate } => command
+            .codec_audio("libmp3lame")
+            .args(["-b:a", &format!("{bitrate}k")])
+            .format("mp3"),
+        TranscodeFormat::Opus { bitrate } => command
+            .codec_audio("libopus")
+            .args(["-b:a", &format!("{bitrate}k")])
+            .format("opus"),
+        TranscodeFormat::Flac => command.codec_audio("flac").format("flac"),
+        TranscodeFormat::M4a => command.codec_audio("copy").format("mp4"),
+    };
+    run(command.overwrite().output(output.to_string_lossy())).await
+}
+
+async fn run(command: &mut FfmpegCommand) -> Result<(), String> {
+    ffmpeg_sidecar::download::auto_download().map_err(|e| e.to_string())?;
+    let mut process = FfmpegProcess(command.spawn().map_err(|e| e.to_string())?);
+    if let Some(mut stderr) = process.0.take_stderr() {
+        spawn_blocking(move || io::copy(&mut stderr, &mut io::sink()));
+    }
+    loop {
+        if let Some(status) = process
+            .0
+            .as_inner_mut()
+            .try_wait()
+            .map_err(|e| e.to_string())?
+        {
+            return status
+                .success()
+                .then_some(())
+                .ok_or("Ffmpeg command failed to run".to_owned());
+        }
+        sleep(Duration::from_millis(100)).await;
+    }
+}
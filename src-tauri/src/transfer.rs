@@ -0,0 +1,147 @@
+use crate::downloads::{DownloadInput, DownloadItem, DownloadStatus};
+use std::path::Path;
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct ListEntry {
+    url: String,
+    #[serde(default)]
+    op: String,
+    #[serde(default)]
+    sub: String,
+    #[serde(default)]
+    title: String,
+    #[serde(default)]
+    status: Option<DownloadStatus>,
+}
+
+impl From<&DownloadItem> for ListEntry {
+    fn from(download: &DownloadItem) -> Self {
+        Self {
+            url: download.url().to_owned(),
+            op: download.op().to_owned(),
+            sub: download.sub().to_owned(),
+            title: download.title().to_owned(),
+            status: Some(download.status()),
+        }
+    }
+}
+
+impl From<ListEntry> for DownloadInput {
+    fn from(entry: ListEntry) -> Self {
+        Self::new(entry.url, entry.op, entry.sub)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListFormat {
+    Json,
+    Csv,
+}
+
+impl ListFormat {
+    const CSV_HEADER: [&str; 5] = ["url", "op", "sub", "title", "status"];
+
+    pub fn from_path(path: &Path) -> Result<Self, String> {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(str::to_ascii_lowercase)
+            .as_deref()
+        {
+            Some("json") => Ok(Self::Json),
+            Some("csv") => Ok(Self::Csv),
+            _ => Err(format!(
+                "Unsupported list format, expected .json or .csv: {}",
+                path.display()
+            )),
+        }
+    }
+
+    pub fn write(self, entries: &[ListEntry]) -> Result<String, String> {
+        match self {
+            Self::Json => serde_json::to_string_pretty(entries).map_err(|e| e.to_string()),
+            Self::Csv => {
+                let mut csv = Self::CSV_HEADER.join(",");
+                csv.push('\n');
+                for entry in entries {
+                    let status = entry.status.map(|s| format!("{s:?}")).unwrap_or_default();
+                    let fields = [&entry.url, &entry.op, &entry.sub, &entry.title, &status];
+                    let row: Vec<String> = fields.iter().map(|f| csv_field(f)).collect();
+                    csv.push_str(&row.join(","));
+                    csv.push('\n');
+                }
+                Ok(csv)
+            }
+        }
+    }
+
+    pub fn read(self, text: &str) -> Result<Vec<ListEntry>, String> {
+        match self {
+            Self::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+            Self::Csv => {
+                let mut rows = csv_rows(text)?.into_iter();
+                let header = rows.next().ok_or("CSV file is empty")?;
+                let column = |name: &str| header.iter().position(|h| h.trim() == name);
+                let url = column("url").ok_or("CSV file has no url column")?;
+                let (op, sub, title) = (column("op"), column("sub"), column("title"));
+                let field = |row: &[String], i: Option<usize>| {
+                    i.and_then(|i| row.get(i)).cloned().unwrap_or_default()
+                };
+                Ok(rows
+                    .filter(|row| row.get(url).is_some_and(|u| !u.trim().is_empty()))
+                    .map(|row| ListEntry {
+                        url: row[url].trim().to_owned(),
+                        op: field(&row, op),
+                        sub: field(&row, sub),
+                        title: field(&row, title),
+                        status: None,
+                    })
+                    .collect())
+            }
+        }
+    }
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+// Handles quoted fields with escaped quotes and embedded newlines, which is
+// all that spreadsheet applications produce for plain text columns.
+fn csv_rows(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut rows = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        match (quoted, c) {
+            (true, '"') if chars.peek() == Some(&'"') => {
+                chars.next();
+                field.push('"');
+            }
+            (true, '"') => quoted = false,
+            (true, c) => field.push(c),
+            (false, '"') if field.is_empty() => quoted = true,
+            (false, ',') => row.push(std::mem::take(&mut field)),
+            (false, '\r') => {}
+            (false, '\n') => {
+                row.push(std::mem::take(&mut field));
+                rows.push(std::mem::take(&mut row));
+            }
+            (false, c) => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("CSV file contains an unterminated quoted field".to_owned());
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push(row);
+    }
+    Ok(rows)
+}
@@ -2,6 +2,7 @@ export interface DownloadInput {
   url: string;
   op: string;
   sub: string;
+  track?: number | null;
 }
 
 export interface DownloadInfo {
@@ -10,13 +11,25 @@ export interface DownloadInfo {
   title: string;
   extension: string;
   headers: Map<string, string>;
+  uploader?: string | null;
+  metadata?: Metadata;
+}
+
+export interface Metadata {
+  description: string | null;
+  date: string | null;
+  keywords: string[];
+  thumbnail: string | null;
 }
 
 export enum DownloadStatus {
   Initial = "Initial",
+  Queued = "Queued",
   Downloading = "Downloading",
   Completed = "Completed",
   Failed = "Failed",
+  Paused = "Paused",
+  Cancelled = "Cancelled",
 }
 
 export interface DownloadOutput {
@@ -25,4 +38,148 @@ export interface DownloadOutput {
   info: DownloadInfo;
   status: DownloadStatus;
   failure: string | null;
+  added_at: number;
+  path: string | null;
+  size: number | null;
+  completed_at: number | null;
+}
+
+export interface DownloadProgress {
+  id: number;
+  received: number;
+  total: number | null;
+  chunks_received: number;
+  chunks_total: number;
+  rate: number;
+}
+
+export function formatBytes(bytes: number): string {
+  const units = ["B", "KB", "MB", "GB"];
+  let value = bytes;
+  let unit = 0;
+  while (value >= 1024 && unit < units.length - 1) {
+    value /= 1024;
+    unit += 1;
+  }
+  return `${value.toFixed(unit === 0 ? 0 : 1)} ${units[unit]}`;
+}
+
+export interface RetryPolicy {
+  retries: number;
+  base_delay_ms: number;
+  max_delay_ms: number;
+}
+
+export interface Settings {
+  max_downloads: number;
+  max_downloads_per_host: number;
+  retry: RetryPolicy;
+  filename_template: string;
+  collision: CollisionPolicy;
+  tags: TagSettings;
+  transcode: TranscodeSettings;
+}
+
+export type TagField =
+  | "Title"
+  | "Artist"
+  | "Album"
+  | "AlbumArtist"
+  | "Genre"
+  | "Comment"
+  | "Date"
+  | "Keywords"
+  | "Source";
+
+export interface TagSettings {
+  mapping: Partial<Record<TagField, string>>;
+  disabled_hosts: string[];
+  disabled_extensions: string[];
+}
+
+export type TranscodeFormat =
+  | { format: "Mp3"; bitrate: number }
+  | { format: "Opus"; bitrate: number }
+  | { format: "Flac" }
+  | { format: "M4a" };
+
+export interface TranscodeSettings {
+  format: TranscodeFormat | null;
+  hosts: Record<string, TranscodeFormat | null>;
+  keep_original: boolean;
+}
+
+export enum CollisionPolicy {
+  Skip = "Skip",
+  Overwrite = "Overwrite",
+  Rename = "Rename",
+  Compare = "Compare",
+}
+
+export interface HostCapabilities {
+  has_title: boolean;
+  provides_chunks: boolean;
+  needs_headers: boolean;
+  supports_playlists: boolean;
+}
+
+export interface HostInfo {
+  name: string;
+  hosts: string[];
+  examples: string[];
+  capabilities: HostCapabilities;
+}
+
+export function isSupportedUrl(url: string, hosts: HostInfo[]): boolean {
+  let hostname: string;
+  try {
+    hostname = new URL(url).hostname.toLowerCase().replace(/\.$/, "");
+  } catch {
+    return false;
+  }
+  return hosts.some((host) =>
+    host.hosts.some(
+      (domain) => hostname === domain || hostname.endsWith(`.${domain}`),
+    ),
+  );
+}
+
+// Reddit posts fill in the category from their subreddit when it is empty.
+export function isRedditPost(url: string): boolean {
+  try {
+    const parsed = new URL(url);
+    const hostname = parsed.hostname.toLowerCase().replace(/\.$/, "");
+    return (
+      (hostname === "reddit.com" || hostname.endsWith(".reddit.com")) &&
+      parsed.pathname.split("/").includes("comments")
+    );
+  } catch {
+    return false;
+  }
+}
+
+export interface PreviewEntry {
+  input: DownloadInput;
+  title: string | null;
+  duration: number | null;
+  added: boolean;
+}
+
+export function formatDuration(seconds: number | null): string {
+  if (seconds === null) return "";
+  const total = Math.round(seconds);
+  const minutes = Math.floor(total / 60);
+  return `${minutes}:${String(total % 60).padStart(2, "0")}`;
+}
+
+export interface AddResult {
+  url: string;
+  download: DownloadOutput | null;
+  error: string | null;
+}
+
+export interface RetagResult {
+  id: number;
+  download: DownloadOutput | null;
+  error: string | null;
 }